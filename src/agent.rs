@@ -1,31 +1,40 @@
 use async_trait::async_trait;
-use std::collections::HashMap;
+use futures::future::{AbortHandle, Abortable};
+use futures::stream::{self, BoxStream, StreamExt};
+use futures::Future;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::time::Duration;
+use tokio::time::delay_for;
 
-use crate::errors::Result;
-use crate::request::{get, put, Body};
-use crate::Client;
+use crate::errors::{Error, Result};
+use crate::health::HealthStatus;
+use crate::request::{get, get_optional, get_raw, get_stream, get_vec, put, Body};
+use crate::{Client, Config, QueryMeta, QueryOptions};
 
-#[serde(default)]
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct AgentCheck {
     pub Node: String,
     pub CheckID: String,
     pub Name: String,
-    pub Status: String,
+    pub Status: HealthStatus,
     pub Notes: String,
     pub Output: String,
     pub ServiceID: String,
     pub ServiceName: String,
 }
 
-#[serde(default)]
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct AgentMember {
     pub Name: String,
     pub Addr: String,
     pub Port: u16,
     pub Tags: HashMap<String, String>,
-    pub pubStatus: usize,
+    /// Serf status, see `AgentMember::status`.
+    pub Status: u8,
     pub ProtocolMin: u8,
     pub ProtocolMax: u8,
     pub ProtocolCur: u8,
@@ -34,30 +43,647 @@ pub struct AgentMember {
     pub DelegateCur: u8,
 }
 
+impl AgentMember {
+    pub fn status(&self) -> MemberStatus {
+        match self.Status {
+            1 => MemberStatus::Alive,
+            2 => MemberStatus::Leaving,
+            3 => MemberStatus::Left,
+            4 => MemberStatus::Failed,
+            _ => MemberStatus::None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemberStatus {
+    None,
+    Alive,
+    Leaving,
+    Left,
+    Failed,
+}
+
+/// Filters for `Agent::members_opts`.
+#[derive(Clone, Debug, Default)]
+pub struct MembersOpts {
+    /// Lists the WAN pool of servers instead of the LAN pool.
+    pub wan: bool,
+    /// Network segment (Consul Enterprise) to list, or `_all` for every segment.
+    pub segment: Option<String>,
+    /// Admin partition (Consul Enterprise) to list, instead of the configured one.
+    pub partition: Option<String>,
+}
+
+/// A change between two successive listings of `Agent::watch_members`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MemberEvent {
+    /// The member appeared or became alive again.
+    Joined(AgentMember),
+    /// An alive member changed, e.g. its address or tags.
+    Updated(AgentMember),
+    /// The member is no longer alive, or was removed from the listing. The last known
+    /// state is reported in the latter case.
+    Left(AgentMember),
+}
+
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct AgentService {
+    pub Kind: String,
     pub ID: String,
     pub Service: String,
     pub Tags: Option<Vec<String>>,
+    pub Meta: HashMap<String, String>,
     pub Port: u16,
     pub Address: String,
+    pub TaggedAddresses: HashMap<String, ServiceAddress>,
+    pub Weights: AgentWeights,
     pub EnableTagOverride: bool,
     pub CreateIndex: u64,
     pub ModifyIndex: u64,
+    pub ContentHash: String,
+    pub Proxy: Option<AgentServiceConnectProxyConfig>,
+    pub Connect: Option<AgentServiceConnect>,
+    pub Namespace: String,
+    pub Partition: String,
+    pub Datacenter: String,
+}
+
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct ServiceAddress {
+    pub Address: String,
+    pub Port: u16,
+}
+
+/// Weights of a service in DNS SRV responses, depending on its health.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentWeights {
+    pub Passing: u32,
+    pub Warning: u32,
+}
+
+impl Default for AgentWeights {
+    fn default() -> AgentWeights {
+        AgentWeights {
+            Passing: 1,
+            Warning: 1,
+        }
+    }
+}
+
+/// A service to register with the local agent.
+///
+/// See https://www.consul.io/api/agent/service.html#register-service
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentServiceRegistration {
+    /// Empty for a typical service, otherwise e.g. `connect-proxy` or `mesh-gateway`.
+    pub Kind: String,
+    /// Defaults to `Name`.
+    pub ID: String,
+    pub Name: String,
+    pub Tags: Vec<String>,
+    pub Port: u16,
+    pub Address: String,
+    pub TaggedAddresses: HashMap<String, ServiceAddress>,
+    pub EnableTagOverride: bool,
+    pub Meta: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Weights: Option<AgentWeights>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Check: Option<AgentServiceCheck>,
+    pub Checks: Vec<AgentServiceCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Proxy: Option<AgentServiceConnectProxyConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Connect: Option<AgentServiceConnect>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Namespace: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Partition: String,
+}
+
+/// A check defined along with a service registration. The fields that are set decide the
+/// kind of check, e.g. `HTTP` and `Interval` for an HTTP check or `TTL` for a TTL check.
+/// Durations are strings such as `"10s"`.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentServiceCheck {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub CheckID: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub Args: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub DockerContainerID: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Shell: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Interval: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Timeout: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub TTL: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub HTTP: String,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub Header: HashMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Method: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Body: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub TCP: String,
+    /// Initial status, `critical` unless set.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Status: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Notes: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub TLSServerName: String,
+    pub TLSSkipVerify: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub GRPC: String,
+    pub GRPCUseTLS: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub AliasNode: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub AliasService: String,
+    pub SuccessBeforePassing: u32,
+    pub FailuresBeforeCritical: u32,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub DeregisterCriticalServiceAfter: String,
+}
+
+impl AgentServiceCheck {
+    /// Checks that a GET of `url` succeeds every `interval`.
+    pub fn http(url: &str, interval: Duration) -> AgentServiceCheck {
+        AgentServiceCheck {
+            HTTP: url.to_owned(),
+            Interval: duration_string(interval),
+            ..Default::default()
+        }
+    }
+
+    /// Checks that `address` (`host:port`) accepts TCP connections every `interval`.
+    pub fn tcp(address: &str, interval: Duration) -> AgentServiceCheck {
+        AgentServiceCheck {
+            TCP: address.to_owned(),
+            Interval: duration_string(interval),
+            ..Default::default()
+        }
+    }
+
+    /// A check turning critical unless it is updated within `ttl`, see `Agent::pass_ttl`.
+    pub fn ttl(ttl: Duration) -> AgentServiceCheck {
+        AgentServiceCheck {
+            TTL: duration_string(ttl),
+            ..Default::default()
+        }
+    }
+
+    /// Queries the gRPC health checking protocol of `target` (`host:port[/service]`)
+    /// every `interval`.
+    pub fn grpc(target: &str, interval: Duration) -> AgentServiceCheck {
+        AgentServiceCheck {
+            GRPC: target.to_owned(),
+            Interval: duration_string(interval),
+            ..Default::default()
+        }
+    }
+
+    /// Runs `args` every `interval`. Requires script checks to be enabled on the agent.
+    pub fn script(args: &[&str], interval: Duration) -> AgentServiceCheck {
+        AgentServiceCheck {
+            Args: args.iter().map(|a| String::from(*a)).collect(),
+            Interval: duration_string(interval),
+            ..Default::default()
+        }
+    }
+
+    /// Mirrors the health of the service `service_id` on the local node.
+    pub fn alias(service_id: &str) -> AgentServiceCheck {
+        AgentServiceCheck {
+            AliasService: service_id.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Formats a duration the way Consul parses it, e.g. `10s` or `1500ms`.
+fn duration_string(d: Duration) -> String {
+    if d.subsec_nanos() == 0 {
+        format!("{}s", d.as_secs())
+    } else {
+        format!("{}ms", d.as_millis())
+    }
+}
+
+/// A check to register on its own, possibly bound to a service.
+///
+/// See https://www.consul.io/api/agent/check.html#register-check
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentCheckRegistration {
+    /// Defaults to the check's `Name`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub ID: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub ServiceID: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Namespace: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Partition: String,
+    /// What to check. Its `Name` is required.
+    #[serde(flatten)]
+    pub Check: AgentServiceCheck,
+}
+
+/// Keeps a TTL check up to date from a health probe, until stopped or dropped.
+#[derive(Debug)]
+pub struct TtlHeartbeat {
+    abort: AbortHandle,
+}
+
+impl TtlHeartbeat {
+    /// Runs `health` every `interval`, marking the check `passing` while it returns `Ok` and
+    /// `critical`, with the error as output, otherwise. `interval` should be well below the
+    /// check's TTL so that a failed update is made up for before it expires.
+    ///
+    /// The heartbeat runs on the current tokio runtime, so this panics outside of one.
+    pub fn spawn<F, Fut, E>(
+        client: &Client,
+        check_id: &str,
+        interval: Duration,
+        mut health: F,
+    ) -> TtlHeartbeat
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = std::result::Result<(), E>> + Send + 'static,
+        E: Display,
+    {
+        let client = client.clone();
+        let check_id = check_id.to_owned();
+        let (abort, registration) = AbortHandle::new_pair();
+        tokio::spawn(Abortable::new(
+            async move {
+                loop {
+                    let (status, output) = match health().await {
+                        Ok(()) => (HealthStatus::Passing, String::new()),
+                        Err(e) => (HealthStatus::Critical, e.to_string()),
+                    };
+                    let _ = client.update_ttl(&check_id, &output, status).await;
+                    delay_for(interval).await;
+                }
+            },
+            registration,
+        ));
+        TtlHeartbeat { abort }
+    }
+
+    /// Stops updating the check, leaving it to expire.
+    pub fn stop(self) {
+        self.abort.abort();
+    }
+}
+
+impl Drop for TtlHeartbeat {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct TtlUpdate<'a> {
+    Status: HealthStatus,
+    Output: &'a str,
+}
+
+/// Connect settings of a service, either native or through a sidecar proxy.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentServiceConnect {
+    pub Native: bool,
+    /// Sidecar proxy registered along with the service. Unset fields are filled in by
+    /// the agent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub SidecarService: Option<Box<AgentServiceRegistration>>,
+}
+
+/// Configuration of a Connect proxy service, i.e. one of kind `connect-proxy`.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentServiceConnectProxyConfig {
+    pub DestinationServiceName: String,
+    pub DestinationServiceID: String,
+    pub LocalServiceAddress: String,
+    pub LocalServicePort: u16,
+    /// Free-form configuration passed to the proxy implementation.
+    pub Config: HashMap<String, Value>,
+    pub Upstreams: Vec<Upstream>,
+    /// Empty, `direct` or `transparent`.
+    pub Mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub MeshGateway: Option<MeshGatewayConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Expose: Option<ExposeConfig>,
+}
+
+/// A service a Connect proxy exposes locally.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Upstream {
+    /// `service` (the default) or `prepared_query`.
+    pub DestinationType: String,
+    pub DestinationNamespace: String,
+    pub DestinationName: String,
+    pub Datacenter: String,
+    pub LocalBindAddress: String,
+    pub LocalBindPort: u16,
+    pub Config: HashMap<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub MeshGateway: Option<MeshGatewayConfig>,
+}
+
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct MeshGatewayConfig {
+    /// Empty, `none`, `local` or `remote`.
+    pub Mode: String,
+}
+
+/// Paths of the service exposed through the proxy without mutual TLS, e.g. for checks.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct ExposeConfig {
+    /// Exposes the paths of the service's HTTP and gRPC checks.
+    pub Checks: bool,
+    pub Paths: Vec<ExposePath>,
+}
+
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct ExposePath {
+    pub ListenerPort: u16,
+    pub Path: String,
+    pub LocalPathPort: u16,
+    /// `http` or `http2`.
+    pub Protocol: String,
+}
+
+/// Response of `/v1/agent/self`.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentSelf {
+    pub Config: AgentSelfConfig,
+    /// The agent's full runtime configuration. Its layout varies between Consul versions.
+    pub DebugConfig: HashMap<String, Value>,
+    pub Coord: Option<Coordinate>,
+    pub Member: AgentMember,
+    pub Stats: AgentStats,
+    pub Meta: HashMap<String, String>,
+}
+
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentSelfConfig {
+    pub Datacenter: String,
+    pub PrimaryDatacenter: String,
+    pub NodeName: String,
+    pub NodeID: String,
+    pub Partition: String,
+    pub Revision: String,
+    pub Server: bool,
+    pub Version: String,
+}
+
+/// Network coordinate of a node, see https://www.consul.io/docs/architecture/coordinates
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Coordinate {
+    pub Vec: Vec<f64>,
+    pub Error: f64,
+    pub Adjustment: f64,
+    pub Height: f64,
+}
+
+/// Diagnostic counters of the agent by subsystem. Consul reports every value as a string.
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentStats {
+    pub agent: HashMap<String, String>,
+    pub build: HashMap<String, String>,
+    pub consul: HashMap<String, String>,
+    pub raft: HashMap<String, String>,
+    pub runtime: HashMap<String, String>,
+    pub serf_lan: HashMap<String, String>,
+    pub serf_wan: HashMap<String, String>,
+}
+
+/// Response of `/v1/agent/host`, as collected by gopsutil.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentHostInfo {
+    pub CPU: Vec<HostCpu>,
+    pub Host: HostDetails,
+    pub Memory: HostMemory,
+    pub Disk: HostDisk,
+    /// Nanoseconds since the epoch.
+    pub CollectionTime: i64,
+    /// One entry per collector that failed. Consul doesn't serialize their messages.
+    pub Errors: Option<Vec<Value>>,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HostCpu {
+    pub cpu: i32,
+    pub vendor_id: String,
+    pub family: String,
+    pub model: String,
+    pub stepping: i32,
+    pub physical_id: String,
+    pub core_id: String,
+    pub cores: i32,
+    pub model_name: String,
+    pub mhz: f64,
+    pub cache_size: i32,
+    pub flags: Vec<String>,
+    pub microcode: String,
+}
+
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HostDetails {
+    pub hostname: String,
+    pub uptime: u64,
+    pub boot_time: u64,
+    pub procs: u64,
+    pub os: String,
+    pub platform: String,
+    pub platform_family: String,
+    pub platform_version: String,
+    pub kernel_version: String,
+    pub kernel_arch: String,
+    pub virtualization_system: String,
+    pub virtualization_role: String,
+    #[serde(alias = "hostid")]
+    pub host_id: String,
+}
+
+/// Memory figures in bytes.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HostMemory {
+    pub total: u64,
+    pub available: u64,
+    pub used: u64,
+    pub used_percent: f64,
+    pub free: u64,
+}
+
+/// Usage of the filesystem holding the agent's data directory, in bytes.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HostDisk {
+    pub path: String,
+    pub fstype: String,
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+    pub used_percent: f64,
+    pub inodes_total: u64,
+    pub inodes_used: u64,
+    pub inodes_free: u64,
+    pub inodes_used_percent: f64,
+}
+
+/// Response of `/v1/agent/version`.
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct AgentVersion {
+    pub SHA: String,
+    pub BuildDate: String,
+    pub HumanVersion: String,
+    pub FIPS: String,
+}
+
+/// Latest interval of the agent's in-memory telemetry, from `/v1/agent/metrics`.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct MetricsInfo {
+    pub Timestamp: String,
+    pub Gauges: Vec<GaugeValue>,
+    pub Points: Vec<PointValue>,
+    pub Counters: Vec<SampledValue>,
+    pub Samples: Vec<SampledValue>,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct GaugeValue {
+    pub Name: String,
+    pub Value: f64,
+    pub Labels: HashMap<String, String>,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct PointValue {
+    pub Name: String,
+    pub Points: Vec<f64>,
+}
+
+/// Aggregate of a counter or timer over the interval.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SampledValue {
+    pub Name: String,
+    pub Count: u64,
+    pub Rate: f64,
+    pub Sum: f64,
+    pub Min: f64,
+    pub Max: f64,
+    pub Mean: f64,
+    pub Stddev: f64,
+    pub Labels: HashMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
 }
 
-//I haven't implemetned https://www.consul.io/api/agent.html#read-configuration
-//I haven't implemetned https://www.consul.io/api/agent.html#stream-logs
 #[async_trait]
 pub trait Agent {
     async fn checks(&self) -> Result<HashMap<String, AgentCheck>>;
-    async fn members(&self, wan: bool) -> Result<AgentMember>;
+    async fn members(&self, wan: bool) -> Result<Vec<AgentMember>>;
+    async fn members_opts(&self, opts: &MembersOpts) -> Result<Vec<AgentMember>>;
+    /// Polls the member list every `interval` and streams the differences.
+    fn watch_members(
+        &self,
+        opts: &MembersOpts,
+        interval: Duration,
+    ) -> BoxStream<'static, Result<MemberEvent>>;
     async fn reload(&self) -> Result<()>;
     async fn maintenance_mode(&self, enable: bool, reason: Option<&str>) -> Result<()>;
     async fn join(&self, address: &str, wan: bool) -> Result<()>;
     async fn leave(&self) -> Result<()>;
     async fn force_leave(&self) -> Result<()>;
+    async fn service_register(&self, registration: &AgentServiceRegistration) -> Result<()>;
+    async fn service_deregister(&self, service_id: &str) -> Result<()>;
+    async fn services(&self) -> Result<HashMap<String, AgentService>>;
+    /// `None` if the agent has no service with that ID.
+    async fn service(
+        &self,
+        service_id: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Option<AgentService>, QueryMeta)>;
+    async fn service_maintenance(
+        &self,
+        service_id: &str,
+        enable: bool,
+        reason: Option<&str>,
+    ) -> Result<()>;
+    async fn check_register(&self, registration: &AgentCheckRegistration) -> Result<()>;
+    async fn check_deregister(&self, check_id: &str) -> Result<()>;
+    async fn pass_ttl(&self, check_id: &str, note: Option<&str>) -> Result<()>;
+    async fn warn_ttl(&self, check_id: &str, note: Option<&str>) -> Result<()>;
+    async fn fail_ttl(&self, check_id: &str, note: Option<&str>) -> Result<()>;
+    /// `status` is `Passing`, `Warning` or `Critical`. Checks can't be put in maintenance,
+    /// so `Maintenance` is rejected.
+    async fn update_ttl(&self, check_id: &str, output: &str, status: HealthStatus) -> Result<()>;
+    async fn self_info(&self) -> Result<AgentSelf>;
+    async fn host(&self) -> Result<AgentHostInfo>;
+    async fn version(&self) -> Result<AgentVersion>;
+    async fn metrics(&self) -> Result<MetricsInfo>;
+    /// Metrics in the Prometheus text exposition format. Requires
+    /// `telemetry.prometheus_retention_time` to be set on the agent.
+    async fn metrics_prometheus(&self) -> Result<String>;
+    /// Streams the agent's log lines at `level` and above, as JSON objects if `json` is set.
+    /// The connection is closed when the stream is dropped. A `ConfigBuilder::timeout`
+    /// also ends the stream once it expires.
+    fn monitor(&self, level: LogLevel, json: bool) -> BoxStream<'static, Result<String>>;
 }
 
 #[async_trait]
@@ -69,15 +695,34 @@ impl Agent for Client {
             .map(|x| x.0)
     }
     /// https://www.consul.io/api/agent.html#list-members
-    async fn members(&self, wan: bool) -> Result<AgentMember> {
-        let mut params = HashMap::new();
-        if wan {
-            params.insert(String::from("wan"), String::from("1"));
-        }
-        get("/v1/agent/members", &self.config, params, None)
-            .await
-            .map(|x| x.0)
+    async fn members(&self, wan: bool) -> Result<Vec<AgentMember>> {
+        self.members_opts(&MembersOpts {
+            wan,
+            ..Default::default()
+        })
+        .await
     }
+
+    /// https://www.consul.io/api/agent.html#list-members
+    async fn members_opts(&self, opts: &MembersOpts) -> Result<Vec<AgentMember>> {
+        list_members(&self.config, opts).await
+    }
+
+    fn watch_members(
+        &self,
+        opts: &MembersOpts,
+        interval: Duration,
+    ) -> BoxStream<'static, Result<MemberEvent>> {
+        let watch = MembersWatch {
+            config: self.config.clone(),
+            opts: opts.clone(),
+            interval,
+            known: None,
+            events: VecDeque::new(),
+        };
+        stream::unfold(watch, |watch| watch.next()).boxed()
+    }
+
     /// https://www.consul.io/api/agent.html#reload-agent
     async fn reload(&self) -> Result<()> {
         put(
@@ -91,7 +736,7 @@ impl Agent for Client {
         .map(|x| x.0)
     }
 
-    /// https://www.consul.io/api/agent.html#reload-agent
+    /// https://www.consul.io/api/agent.html#enable-maintenance-mode
     async fn maintenance_mode(&self, enable: bool, reason: Option<&str>) -> Result<()> {
         let mut params = HashMap::new();
         let enable_str = if enable {
@@ -99,7 +744,7 @@ impl Agent for Client {
         } else {
             String::from("false")
         };
-        params.insert(String::from("enabled"), enable_str);
+        params.insert(String::from("enable"), enable_str);
         if let Some(r) = reason {
             params.insert(String::from("reason"), r.to_owned());
         }
@@ -151,4 +796,335 @@ impl Agent for Client {
         .await
         .map(|x| x.0)
     }
+
+    /// https://www.consul.io/api/agent/service.html#register-service
+    async fn service_register(&self, registration: &AgentServiceRegistration) -> Result<()> {
+        put(
+            "/v1/agent/service/register",
+            Some(Body::AsJson(registration)),
+            &self.config,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent/service.html#deregister-service
+    async fn service_deregister(&self, service_id: &str) -> Result<()> {
+        let path = format!("/v1/agent/service/deregister/{}", service_id);
+        put(
+            &path,
+            None as Option<Body<()>>,
+            &self.config,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent/service.html#list-services
+    async fn services(&self) -> Result<HashMap<String, AgentService>> {
+        get("/v1/agent/services", &self.config, HashMap::new(), None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent/service.html#get-service-configuration
+    async fn service(
+        &self,
+        service_id: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Option<AgentService>, QueryMeta)> {
+        let path = format!("/v1/agent/service/{}", service_id);
+        get_optional(&path, &self.config, HashMap::new(), q).await
+    }
+
+    /// https://www.consul.io/api/agent/service.html#enable-maintenance-mode
+    async fn service_maintenance(
+        &self,
+        service_id: &str,
+        enable: bool,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert(String::from("enable"), enable.to_string());
+        if let Some(r) = reason {
+            params.insert(String::from("reason"), r.to_owned());
+        }
+        let path = format!("/v1/agent/service/maintenance/{}", service_id);
+        put(&path, None as Option<Body<()>>, &self.config, params, None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent/check.html#register-check
+    async fn check_register(&self, registration: &AgentCheckRegistration) -> Result<()> {
+        put(
+            "/v1/agent/check/register",
+            Some(Body::AsJson(registration)),
+            &self.config,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent/check.html#deregister-check
+    async fn check_deregister(&self, check_id: &str) -> Result<()> {
+        let path = format!("/v1/agent/check/deregister/{}", check_id);
+        put(
+            &path,
+            None as Option<Body<()>>,
+            &self.config,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent/check.html#ttl-check-pass
+    async fn pass_ttl(&self, check_id: &str, note: Option<&str>) -> Result<()> {
+        mark_ttl(self, "pass", check_id, note).await
+    }
+
+    /// https://www.consul.io/api/agent/check.html#ttl-check-warn
+    async fn warn_ttl(&self, check_id: &str, note: Option<&str>) -> Result<()> {
+        mark_ttl(self, "warn", check_id, note).await
+    }
+
+    /// https://www.consul.io/api/agent/check.html#ttl-check-fail
+    async fn fail_ttl(&self, check_id: &str, note: Option<&str>) -> Result<()> {
+        mark_ttl(self, "fail", check_id, note).await
+    }
+
+    /// https://www.consul.io/api/agent/check.html#ttl-check-update
+    async fn update_ttl(&self, check_id: &str, output: &str, status: HealthStatus) -> Result<()> {
+        if status == HealthStatus::Maintenance {
+            return Err(Error::InvalidArgument(
+                "maintenance isn't a check status, use node or service maintenance instead",
+            ));
+        }
+        let path = format!("/v1/agent/check/update/{}", check_id);
+        let update = TtlUpdate {
+            Status: status,
+            Output: output,
+        };
+        put(
+            &path,
+            Some(Body::AsJson(update)),
+            &self.config,
+            HashMap::new(),
+            None,
+        )
+        .await
+        .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#read-configuration
+    async fn self_info(&self) -> Result<AgentSelf> {
+        get("/v1/agent/self", &self.config, HashMap::new(), None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#retrieve-host-information
+    async fn host(&self) -> Result<AgentHostInfo> {
+        get("/v1/agent/host", &self.config, HashMap::new(), None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#retrieve-version-information
+    async fn version(&self) -> Result<AgentVersion> {
+        get("/v1/agent/version", &self.config, HashMap::new(), None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#view-metrics
+    async fn metrics(&self) -> Result<MetricsInfo> {
+        get("/v1/agent/metrics", &self.config, HashMap::new(), None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#view-metrics
+    async fn metrics_prometheus(&self) -> Result<String> {
+        let mut params = HashMap::new();
+        params.insert(String::from("format"), String::from("prometheus"));
+        get_raw("/v1/agent/metrics", &self.config, params, None)
+            .await
+            .map(|x| x.0)
+    }
+
+    /// https://www.consul.io/api/agent.html#stream-logs
+    fn monitor(&self, level: LogLevel, json: bool) -> BoxStream<'static, Result<String>> {
+        let mut params = HashMap::new();
+        params.insert(String::from("loglevel"), String::from(level.as_str()));
+        if json {
+            params.insert(String::from("logjson"), String::from("true"));
+        }
+        let monitor = LogMonitor {
+            config: self.config.clone(),
+            params,
+            body: None,
+            buffer: Vec::new(),
+            done: false,
+        };
+        stream::unfold(monitor, |monitor| monitor.next()).boxed()
+    }
+}
+
+async fn mark_ttl(client: &Client, state: &str, check_id: &str, note: Option<&str>) -> Result<()> {
+    let mut params = HashMap::new();
+    if let Some(n) = note {
+        params.insert(String::from("note"), n.to_owned());
+    }
+    let path = format!("/v1/agent/check/{}/{}", state, check_id);
+    put(
+        &path,
+        None as Option<Body<()>>,
+        &client.config,
+        params,
+        None,
+    )
+    .await
+    .map(|x| x.0)
+}
+
+async fn list_members(config: &Config, opts: &MembersOpts) -> Result<Vec<AgentMember>> {
+    let mut params = HashMap::new();
+    if opts.wan {
+        params.insert(String::from("wan"), String::from("1"));
+    }
+    if let Some(ref segment) = opts.segment {
+        params.insert(String::from("segment"), segment.to_owned());
+    }
+    let q = QueryOptions {
+        partition: opts.partition.clone(),
+        ..Default::default()
+    };
+    get_vec("/v1/agent/members", config, params, Some(&q))
+        .await
+        .map(|x| x.0)
+}
+
+struct MembersWatch {
+    config: Config,
+    opts: MembersOpts,
+    interval: Duration,
+    /// Members of the last listing by name, `None` before the first one.
+    known: Option<HashMap<String, AgentMember>>,
+    events: VecDeque<MemberEvent>,
+}
+
+impl MembersWatch {
+    async fn next(mut self) -> Option<(Result<MemberEvent>, MembersWatch)> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some((Ok(event), self));
+            }
+            if self.known.is_some() {
+                delay_for(self.interval).await;
+            }
+            let members = match list_members(&self.config, &self.opts).await {
+                Ok(members) => members,
+                Err(e) => {
+                    // Wait an interval before polling again. An earlier listing is kept to
+                    // diff against.
+                    self.known.get_or_insert_with(HashMap::new);
+                    return Some((Err(e), self));
+                }
+            };
+            let current: HashMap<String, AgentMember> =
+                members.into_iter().map(|m| (m.Name.clone(), m)).collect();
+            let known = self.known.take().unwrap_or_default();
+            self.events = diff_members(&known, &current);
+            self.known = Some(current);
+        }
+    }
+}
+
+fn diff_members(
+    known: &HashMap<String, AgentMember>,
+    current: &HashMap<String, AgentMember>,
+) -> VecDeque<MemberEvent> {
+    let alive = |m: &AgentMember| m.status() == MemberStatus::Alive;
+    let mut events = VecDeque::new();
+    for (name, member) in current {
+        let old = known.get(name);
+        match (old.map(alive) == Some(true), alive(member)) {
+            (true, true) if old != Some(member) => {
+                events.push_back(MemberEvent::Updated(member.clone()))
+            }
+            (true, false) => events.push_back(MemberEvent::Left(member.clone())),
+            (false, true) => events.push_back(MemberEvent::Joined(member.clone())),
+            _ => {}
+        }
+    }
+    for (name, old) in known {
+        if !current.contains_key(name) && alive(old) {
+            events.push_back(MemberEvent::Left(old.clone()));
+        }
+    }
+    events
+}
+
+/// Splits the chunked body of `/v1/agent/monitor` into lines.
+struct LogMonitor {
+    config: Config,
+    params: HashMap<String, String>,
+    body: Option<BoxStream<'static, Result<Vec<u8>>>>,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl LogMonitor {
+    async fn next(mut self) -> Option<(Result<String>, LogMonitor)> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+                let rest = self.buffer.split_off(end + 1);
+                let line = std::mem::replace(&mut self.buffer, rest);
+                return Some((Ok(decode_line(&line)), self));
+            }
+            if self.done {
+                if self.buffer.is_empty() {
+                    return None;
+                }
+                let line = std::mem::take(&mut self.buffer);
+                return Some((Ok(decode_line(&line)), self));
+            }
+
+            let body = match self.body {
+                Some(ref mut body) => body,
+                None => {
+                    let path = "/v1/agent/monitor";
+                    match get_stream(path, &self.config, self.params.clone(), None).await {
+                        Ok(body) => self.body.get_or_insert(body),
+                        Err(e) => {
+                            self.done = true;
+                            return Some((Err(e), self));
+                        }
+                    }
+                }
+            };
+            match body.next().await {
+                Some(Ok(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some((Err(e), self));
+                }
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+fn decode_line(line: &[u8]) -> String {
+    let line = String::from_utf8_lossy(line);
+    line.trim_end_matches(&['\r', '\n'][..]).to_owned()
 }
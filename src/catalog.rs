@@ -1,81 +1,184 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
 
-use crate::agent::{AgentCheck, AgentService};
+use crate::agent::{
+    AgentService, AgentServiceConnect, AgentServiceConnectProxyConfig, AgentWeights, ServiceAddress,
+};
 use crate::errors::Result;
-use crate::request::{get, put, Body};
+use crate::health::HealthCheck;
+use crate::request::{get, get_optional, get_vec, null_as_default, put, Body};
 use crate::{Client, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
 
-#[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
-pub struct Weights {
-    Passing: u32,
-    Warning: u32,
-}
+/// Kept under its old name, the catalog and the agent share one weights type.
+pub use crate::agent::AgentWeights as Weights;
 
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct Node {
-    ID: String,
-    Node: String,
-    Address: String,
-    Datacenter: String,
-    TaggedAddresses: HashMap<String, String>,
-    Meta: HashMap<String, String>,
-    CreateIndex: u64,
-    ModifyIndex: u64,
+    pub ID: String,
+    pub Node: String,
+    pub Address: String,
+    pub Datacenter: String,
+    #[serde(deserialize_with = "null_as_default")]
+    pub TaggedAddresses: HashMap<String, String>,
+    #[serde(deserialize_with = "null_as_default")]
+    pub Meta: HashMap<String, String>,
+    pub Partition: String,
+    pub CreateIndex: u64,
+    pub ModifyIndex: u64,
 }
 
+/// A service instance together with the node it runs on.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct CatalogService {
-    ID: String,
-    Node: String,
-    Address: String,
-    Datacenter: String,
-    TaggedAddresses: HashMap<String, String>,
-    NodeMeta: HashMap<String, String>,
-    ServiceID: String,
-    ServiceName: String,
-    ServiceAddress: String,
-    ServiceTags: Vec<String>,
-    ServiceMeta: HashMap<String, String>,
-    ServicePort: u32,
-    ServiceWeights: Weights,
-    ServiceEnableTagOverride: bool,
-    CreateIndex: u64,
-    ModifyIndex: u64,
+    pub ID: String,
+    pub Node: String,
+    pub Address: String,
+    pub Datacenter: String,
+    #[serde(deserialize_with = "null_as_default")]
+    pub TaggedAddresses: HashMap<String, String>,
+    #[serde(deserialize_with = "null_as_default")]
+    pub NodeMeta: HashMap<String, String>,
+    pub ServiceKind: String,
+    pub ServiceID: String,
+    pub ServiceName: String,
+    pub ServiceAddress: String,
+    #[serde(deserialize_with = "null_as_default")]
+    pub ServiceTaggedAddresses: HashMap<String, ServiceAddress>,
+    #[serde(deserialize_with = "null_as_default")]
+    pub ServiceTags: Vec<String>,
+    #[serde(deserialize_with = "null_as_default")]
+    pub ServiceMeta: HashMap<String, String>,
+    pub ServicePort: u16,
+    pub ServiceWeights: AgentWeights,
+    pub ServiceEnableTagOverride: bool,
+    pub ServiceProxy: Option<AgentServiceConnectProxyConfig>,
+    pub Namespace: String,
+    pub Partition: String,
+    pub CreateIndex: u64,
+    pub ModifyIndex: u64,
 }
 
+/// A node and the services registered on it.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct CatalogNode {
-    Node: Option<Node>,
-    Services: HashMap<String, AgentService>,
+    pub Node: Option<Node>,
+    #[serde(deserialize_with = "null_as_default")]
+    pub Services: HashMap<String, AgentService>,
+}
+
+/// A node and the services registered on it, as a list rather than keyed by ID.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct CatalogNodeServiceList {
+    pub Node: Option<Node>,
+    #[serde(deserialize_with = "null_as_default")]
+    pub Services: Vec<AgentService>,
+}
+
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct CompoundServiceName {
+    pub Name: String,
+    pub Namespace: String,
+    pub Partition: String,
+}
+
+/// A service routed through a terminating or ingress gateway.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct GatewayService {
+    pub Gateway: CompoundServiceName,
+    pub Service: CompoundServiceName,
+    /// `terminating-gateway` or `ingress-gateway`.
+    pub GatewayKind: String,
+    /// Listener port, for ingress gateways.
+    pub Port: u16,
+    pub Protocol: String,
+    #[serde(deserialize_with = "null_as_default")]
+    pub Hosts: Vec<String>,
+    pub CAFile: String,
+    pub CertFile: String,
+    pub KeyFile: String,
+    pub SNI: String,
+    /// Whether the service was matched by a `*` entry of the gateway's config.
+    pub FromWildcard: bool,
 }
 
+/// Registers a node, and optionally a service and checks on it, directly in the catalog.
+/// This is how external services, which don't run a Consul agent, are made discoverable.
+///
+/// See https://www.consul.io/api/catalog.html#register-entity
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct CatalogRegistration {
-    ID: String,
-    Node: String,
-    Address: String,
-    TaggedAddresses: HashMap<String, String>,
-    NodeMeta: HashMap<String, String>,
-    Datacenter: String,
-    Service: Option<AgentService>,
-    Check: Option<AgentCheck>,
-    SkipNodeUpdate: bool,
+    pub ID: String,
+    pub Node: String,
+    pub Address: String,
+    pub TaggedAddresses: HashMap<String, String>,
+    pub NodeMeta: HashMap<String, String>,
+    pub Datacenter: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Service: Option<CatalogRegistrationService>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Check: Option<HealthCheck>,
+    /// Checks of the node, or of `Service` when their `ServiceID` matches its ID.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub Checks: Vec<HealthCheck>,
+    /// Leaves the node's address and meta untouched if the node already exists.
+    pub SkipNodeUpdate: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Partition: String,
+}
+
+/// The service of a `CatalogRegistration`. Unlike `AgentService` it leaves out the fields
+/// Consul fills in itself, such as the indexes and the content hash.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct CatalogRegistrationService {
+    /// Empty for a typical service, otherwise e.g. `connect-proxy` or `mesh-gateway`.
+    pub Kind: String,
+    /// Defaults to `Service`.
+    pub ID: String,
+    pub Service: String,
+    pub Tags: Vec<String>,
+    pub Meta: HashMap<String, String>,
+    pub Port: u16,
+    pub Address: String,
+    pub TaggedAddresses: HashMap<String, ServiceAddress>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Weights: Option<AgentWeights>,
+    pub EnableTagOverride: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Proxy: Option<AgentServiceConnectProxyConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Connect: Option<AgentServiceConnect>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Namespace: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Partition: String,
 }
 
+/// Removes a node from the catalog, or only one of its services or checks when
+/// `ServiceID` or `CheckID` is set.
+///
+/// See https://www.consul.io/api/catalog.html#deregister-entity
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct CatalogDeregistration {
-    Node: String,
-    Address: String,
-    Datacenter: String,
-    ServiceID: String,
-    CheckID: String,
+    pub Node: String,
+    pub Address: String,
+    pub Datacenter: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub ServiceID: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub CheckID: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Namespace: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Partition: String,
 }
 
 #[async_trait]
@@ -84,18 +187,47 @@ pub trait Catalog {
         &self,
         reg: &CatalogRegistration,
         q: Option<&WriteOptions>,
-    ) -> Result<((), WriteMeta)>;
+    ) -> Result<(bool, WriteMeta)>;
     async fn deregister(
         &self,
         dereg: &CatalogDeregistration,
         q: Option<&WriteOptions>,
-    ) -> Result<((), WriteMeta)>;
+    ) -> Result<(bool, WriteMeta)>;
     async fn datacenters(&self) -> Result<(Vec<String>, QueryMeta)>;
     async fn nodes(&self, q: Option<&QueryOptions>) -> Result<(Vec<Node>, QueryMeta)>;
     async fn services(
         &self,
         q: Option<&QueryOptions>,
     ) -> Result<(HashMap<String, Vec<String>>, QueryMeta)>;
+    async fn service(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Vec<CatalogService>, QueryMeta)>;
+    async fn connect(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Vec<CatalogService>, QueryMeta)>;
+    /// Returns `None` if the node isn't registered.
+    async fn node(
+        &self,
+        node: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Option<CatalogNode>, QueryMeta)>;
+    /// Returns `None` if the node isn't registered.
+    async fn node_services(
+        &self,
+        node: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Option<CatalogNodeServiceList>, QueryMeta)>;
+    async fn gateway_services(
+        &self,
+        gateway: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Vec<GatewayService>, QueryMeta)>;
 }
 
 #[async_trait]
@@ -105,9 +237,9 @@ impl Catalog for Client {
         &self,
         reg: &CatalogRegistration,
         q: Option<&WriteOptions>,
-    ) -> Result<((), WriteMeta)> {
+    ) -> Result<(bool, WriteMeta)> {
         put(
-            "/v1/session/create",
+            "/v1/catalog/register",
             Some(Body::AsJson(reg)),
             &self.config,
             HashMap::new(),
@@ -121,7 +253,7 @@ impl Catalog for Client {
         &self,
         dereg: &CatalogDeregistration,
         q: Option<&WriteOptions>,
-    ) -> Result<((), WriteMeta)> {
+    ) -> Result<(bool, WriteMeta)> {
         put(
             "/v1/catalog/deregister",
             Some(Body::AsJson(dereg)),
@@ -148,10 +280,74 @@ impl Catalog for Client {
         get("/v1/catalog/nodes", &self.config, HashMap::new(), q).await
     }
 
+    /// https://www.consul.io/api/catalog.html#list-services
     async fn services(
         &self,
         q: Option<&QueryOptions>,
     ) -> Result<(HashMap<String, Vec<String>>, QueryMeta)> {
         get("/v1/catalog/services", &self.config, HashMap::new(), q).await
     }
+
+    /// https://www.consul.io/api/catalog.html#list-nodes-for-service
+    async fn service(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Vec<CatalogService>, QueryMeta)> {
+        let path = format!("/v1/catalog/service/{}", service);
+        get_vec(&path, &self.config, tag_params(tag), q).await
+    }
+
+    /// Instances of a service that can be reached through Connect, i.e. its proxies and
+    /// native Connect instances.
+    ///
+    /// https://www.consul.io/api/catalog.html#list-nodes-for-connect-capable-service
+    async fn connect(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Vec<CatalogService>, QueryMeta)> {
+        let path = format!("/v1/catalog/connect/{}", service);
+        get_vec(&path, &self.config, tag_params(tag), q).await
+    }
+
+    /// https://www.consul.io/api/catalog.html#retrieve-map-of-services-for-a-node
+    async fn node(
+        &self,
+        node: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Option<CatalogNode>, QueryMeta)> {
+        let path = format!("/v1/catalog/node/{}", node);
+        get_optional(&path, &self.config, HashMap::new(), q).await
+    }
+
+    /// https://www.consul.io/api/catalog.html#list-services-for-node
+    async fn node_services(
+        &self,
+        node: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Option<CatalogNodeServiceList>, QueryMeta)> {
+        let path = format!("/v1/catalog/node-services/{}", node);
+        get_optional(&path, &self.config, HashMap::new(), q).await
+    }
+
+    /// https://www.consul.io/api/catalog.html#list-services-for-gateway
+    async fn gateway_services(
+        &self,
+        gateway: &str,
+        q: Option<&QueryOptions>,
+    ) -> Result<(Vec<GatewayService>, QueryMeta)> {
+        let path = format!("/v1/catalog/gateway-services/{}", gateway);
+        get_vec(&path, &self.config, HashMap::new(), q).await
+    }
+}
+
+fn tag_params(tag: Option<&str>) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    if let Some(tag) = tag {
+        params.insert(String::from("tag"), tag.to_owned());
+    }
+    params
 }
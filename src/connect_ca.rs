@@ -6,8 +6,8 @@ use crate::errors::Result;
 use crate::request::{get, put, Body};
 use crate::{Client, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
 
-#[serde(default)]
 #[derive(Default, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct CAConfig {
     Provider: String,
     Config: Value,
@@ -15,16 +15,16 @@ pub struct CAConfig {
     ModifyIndex: u64,
 }
 
-#[serde(default)]
 #[derive(Default, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct CARootList {
     ActiveRootID: String,
     TrustDomain: String,
     Roots: Vec<CARoot>,
 }
 
-#[serde(default)]
 #[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct CARoot {
     ID: String,
     Name: String,
@@ -0,0 +1,136 @@
+use futures::future;
+use futures::stream::{BoxStream, StreamExt};
+use futures::Future;
+
+use crate::errors::{Error, Result};
+use crate::kv::{KVPair, KV};
+use crate::lock::{Lock, LockGuard, LockOptions, LOCK_FLAG_VALUE};
+use crate::{Client, QueryOptions};
+
+/// The current holder of an election key.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Leader {
+    pub session: String,
+    /// Payload the leader stored in the key when campaigning.
+    pub value: Option<Vec<u8>>,
+    pub lock_index: Option<u64>,
+}
+
+/// Leader election on a single KV key, using the same lock protocol as `Lock`.
+///
+/// See https://www.consul.io/docs/guides/leader-election.html
+#[derive(Clone, Debug)]
+pub struct Election {
+    client: Client,
+    options: LockOptions,
+}
+
+impl Election {
+    pub fn new(client: &Client, key: &str) -> Election {
+        Election::with_options(client, LockOptions::new(key))
+    }
+
+    /// Uses `options` for the underlying lock. Its `value` is replaced by the payload
+    /// passed to `campaign`.
+    pub fn with_options(client: &Client, options: LockOptions) -> Election {
+        Election {
+            client: client.clone(),
+            options,
+        }
+    }
+
+    /// Waits until this candidate becomes the leader, publishing `value` in the key.
+    pub async fn campaign(&self, value: &[u8]) -> Result<Leadership> {
+        let options = LockOptions {
+            value: Some(value.to_vec()),
+            ..self.options.clone()
+        };
+        Lock::new(&self.client, options)
+            .acquire()
+            .await
+            .map(|guard| Leadership { guard })
+    }
+
+    /// Returns the current leader, if any.
+    pub async fn current_leader(&self) -> Result<Option<Leader>> {
+        let (pair, _) = self.client.get(&self.options.key, None).await?;
+        leader_from_pair(pair)
+    }
+
+    /// Streams the leader every time it changes, including `None` while nobody leads.
+    pub fn observe(&self) -> BoxStream<'static, Result<Option<Leader>>> {
+        let query = QueryOptions {
+            wait_time: Some(self.options.lock_wait_time),
+            ..Default::default()
+        };
+        self.client
+            .watch(&self.options.key, Some(&query))
+            .map(|r| r.and_then(|(pair, _)| leader_from_pair(pair)))
+            .scan(None, |last: &mut Option<Option<Leader>>, item| {
+                let changed = match item {
+                    Ok(ref leader) if last.as_ref() == Some(leader) => false,
+                    Ok(ref leader) => {
+                        *last = Some(leader.clone());
+                        true
+                    }
+                    Err(_) => true,
+                };
+                future::ready(Some(if changed { Some(item) } else { None }))
+            })
+            .filter_map(future::ready)
+            .boxed()
+    }
+}
+
+/// Held while this candidate is the leader. Leadership is resigned when dropped.
+#[derive(Debug)]
+pub struct Leadership {
+    guard: LockGuard,
+}
+
+impl Leadership {
+    pub fn session(&self) -> &str {
+        self.guard.session()
+    }
+
+    /// The key's `LockIndex` when leadership was won, usable as a fencing token.
+    pub fn lock_index(&self) -> Option<u64> {
+        self.guard.lock_index()
+    }
+
+    /// Resolves once leadership has been lost.
+    pub fn lost(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.guard.lost()
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.guard.is_lost()
+    }
+
+    /// Steps down so another candidate can take over.
+    pub async fn resign(self) -> Result<()> {
+        self.guard.release().await
+    }
+}
+
+fn leader_from_pair(pair: Option<KVPair>) -> Result<Option<Leader>> {
+    let pair = match pair {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    if pair.Flags != Some(LOCK_FLAG_VALUE) {
+        return Err(Error::Conflict {
+            key: pair.Key,
+            kind: "lock",
+        });
+    }
+    let session = match pair.Session {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    Ok(Some(Leader {
+        session,
+        value: pair.Value,
+        lock_index: pair.LockIndex,
+    }))
+}
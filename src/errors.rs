@@ -1,11 +1,164 @@
-error_chain! {
-    errors{
-        BadUrl{
-            description("")
+use reqwest::StatusCode;
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+
+use crate::txn::TxnError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The underlying HTTP client could not be built from the configuration.
+    #[error("failed to build HTTP client: {0}")]
+    Client(#[source] reqwest::Error),
+    /// The configured agent address can't be used as a base URL.
+    #[error("invalid agent address {address:?}: {reason}")]
+    InvalidAddress { address: String, reason: String },
+    /// The TLS connector couldn't be built from the loaded certificates.
+    #[error("invalid TLS configuration: {0}")]
+    Tls(#[source] native_tls::Error),
+    /// A CA certificate, client certificate or key couldn't be read or parsed.
+    #[error("failed to load TLS material from {}: {source}", .path.display())]
+    TlsMaterial {
+        path: PathBuf,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    #[error("failed to read token file {}: {source}", .path.display())]
+    TokenFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid value for environment variable {name}: {value:?}")]
+    InvalidEnvVar { name: &'static str, value: String },
+    #[error("invalid URL for {path}: {source}")]
+    InvalidUrl {
+        path: String,
+        source: url::ParseError,
+    },
+    /// The request could not be sent or its response could not be read.
+    #[error("HTTP request to {path} failed: {source}")]
+    Http {
+        path: String,
+        source: reqwest::Error,
+    },
+    /// The request couldn't be sent over the agent's unix socket.
+    #[error("HTTP request to {path} over {} failed: {source}", .socket.display())]
+    Socket {
+        path: String,
+        socket: PathBuf,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// The request couldn't be sent over HTTPS with the certificate verified against
+    /// `TlsConfig::server_name`.
+    #[error("HTTPS request to {path} failed: {source}")]
+    Https {
+        path: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// No response arrived within `Config::timeout`, whichever way the request was sent.
+    #[error("HTTP request to {path} timed out: {source}")]
+    Timeout {
+        path: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// Consul answered with a non-success status, e.g. 403 for an ACL denial.
+    #[error("Consul returned {status} for {path}: {body}")]
+    Status {
+        path: String,
+        status: StatusCode,
+        body: String,
+    },
+    #[error("failed to decode response from {path}: {source}")]
+    Decode {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[error("invalid {header} header in response from {path}: {value:?}")]
+    InvalidHeader {
+        path: String,
+        header: &'static str,
+        value: String,
+    },
+    #[error("unexpected response from {path}: {message}")]
+    UnexpectedResponse { path: String, message: &'static str },
+    #[error("failed to encode request body: {0}")]
+    Encode(#[source] serde_json::Error),
+    /// A KV value couldn't be interpreted as requested.
+    #[error("invalid value for key {key}: {source}")]
+    InvalidValue {
+        key: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    #[error("invalid argument: {0}")]
+    InvalidArgument(&'static str),
+    /// Consul rolled back a transaction. Each error points at the operation that failed.
+    #[error("transaction rolled back: {}", describe_txn_errors(.0))]
+    TransactionRolledBack(Vec<TxnError>),
+    /// The key exists but wasn't written by the expected lock or semaphore.
+    #[error("key {key} is not used as a {kind}")]
+    Conflict { key: String, kind: &'static str },
+    #[error("semaphore limit conflict (lock: {lock}, local: {local})")]
+    SemaphoreLimitConflict { lock: usize, local: usize },
+    #[error("failed to acquire {key}")]
+    NotAcquired { key: String },
+    #[error("session {id} expired")]
+    SessionExpired { id: String },
+}
+
+impl Error {
+    pub(crate) fn http(path: &str, source: reqwest::Error) -> Error {
+        if source.is_timeout() {
+            Error::Timeout {
+                path: path.to_owned(),
+                source: Box::new(source),
+            }
+        } else {
+            Error::Http {
+                path: path.to_owned(),
+                source,
+            }
+        }
+    }
+
+    pub(crate) fn tls_material<E>(path: &Path, source: E) -> Error
+    where
+        E: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        Error::TlsMaterial {
+            path: path.to_owned(),
+            source: source.into(),
         }
-        ConsulError{
-            description("")
+    }
+
+    /// The status Consul answered with, if the error is due to a non-success response.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            _ => None,
         }
     }
 
+    /// The API path of the request that failed, if the error came from a request.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::InvalidUrl { path, .. }
+            | Error::Http { path, .. }
+            | Error::Socket { path, .. }
+            | Error::Https { path, .. }
+            | Error::Timeout { path, .. }
+            | Error::Status { path, .. }
+            | Error::Decode { path, .. }
+            | Error::InvalidHeader { path, .. }
+            | Error::UnexpectedResponse { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+fn describe_txn_errors(errors: &[TxnError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("op {}: {}", e.OpIndex, e.What))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
@@ -0,0 +1,171 @@
+use std::fmt;
+
+/// A field of the listed objects, e.g. `ServiceTags` or `Service.Meta`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Selector(String);
+
+impl Selector {
+    /// A field, or a path to a nested field separated by dots.
+    pub fn new(field: &str) -> Selector {
+        Selector(field.to_owned())
+    }
+
+    /// The value of `key` in a map field such as `NodeMeta`.
+    pub fn key(self, key: &str) -> Selector {
+        let is_identifier = key.starts_with(|c: char| c.is_ascii_alphabetic())
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if is_identifier {
+            Selector(format!("{}.{}", self.0, key))
+        } else {
+            Selector(format!("{}[{}]", self.0, quote(key)))
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Match,
+    Not,
+    And,
+    Or,
+}
+
+/// A filter expression, built so that selectors and values are always properly quoted.
+///
+/// ```
+/// use consul::filter::{Filter, Selector};
+///
+/// let filter = Filter::contains(Selector::new("ServiceTags"), "primary")
+///     .and(Filter::eq(Selector::new("ServiceMeta").key("version"), "2"));
+/// assert_eq!(
+///     filter.to_string(),
+///     r#"ServiceTags contains "primary" and ServiceMeta.version == "2""#
+/// );
+/// ```
+///
+/// See https://www.consul.io/api/features/filtering.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Filter {
+    expression: String,
+    kind: Kind,
+}
+
+impl Filter {
+    fn matching(selector: Selector, operator: &str, value: &str) -> Filter {
+        Filter {
+            expression: format!("{} {} {}", selector, operator, quote(value)),
+            kind: Kind::Match,
+        }
+    }
+
+    pub fn eq(selector: Selector, value: &str) -> Filter {
+        Filter::matching(selector, "==", value)
+    }
+
+    pub fn ne(selector: Selector, value: &str) -> Filter {
+        Filter::matching(selector, "!=", value)
+    }
+
+    /// Matches if a list such as `ServiceTags` holds `value`, or a map has it as a key.
+    pub fn contains(selector: Selector, value: &str) -> Filter {
+        Filter::matching(selector, "contains", value)
+    }
+
+    pub fn not_contains(selector: Selector, value: &str) -> Filter {
+        Filter::matching(selector, "not contains", value)
+    }
+
+    /// Matches if the field matches the regular expression `pattern`.
+    pub fn matches(selector: Selector, pattern: &str) -> Filter {
+        Filter::matching(selector, "matches", pattern)
+    }
+
+    pub fn not_matches(selector: Selector, pattern: &str) -> Filter {
+        Filter::matching(selector, "not matches", pattern)
+    }
+
+    pub fn is_empty(selector: Selector) -> Filter {
+        Filter {
+            expression: format!("{} is empty", selector),
+            kind: Kind::Match,
+        }
+    }
+
+    pub fn is_not_empty(selector: Selector) -> Filter {
+        Filter {
+            expression: format!("{} is not empty", selector),
+            kind: Kind::Match,
+        }
+    }
+
+    pub fn and(self, other: Filter) -> Filter {
+        Filter {
+            expression: format!(
+                "{} and {}",
+                self.operand(Kind::And),
+                other.operand(Kind::And)
+            ),
+            kind: Kind::And,
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Filter {
+        Filter {
+            expression: format!("{} or {}", self.operand(Kind::Or), other.operand(Kind::Or)),
+            kind: Kind::Or,
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Filter {
+        Filter {
+            expression: format!("not {}", self.operand(Kind::Not)),
+            kind: Kind::Not,
+        }
+    }
+
+    /// The expression, in parentheses unless it binds at least as tightly as `parent`.
+    fn operand(&self, parent: Kind) -> String {
+        let grouped = matches!(
+            (self.kind, parent),
+            (Kind::Or, Kind::And) | (Kind::Or, Kind::Not) | (Kind::And, Kind::Not)
+        );
+        if grouped {
+            format!("({})", self.expression)
+        } else {
+            self.expression.clone()
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl From<Filter> for String {
+    fn from(filter: Filter) -> String {
+        filter.expression
+    }
+}
+
+/// A double quoted string literal.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
@@ -1,27 +1,124 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::fmt;
 
 use crate::agent::AgentService;
-use crate::errors::Result;
-use crate::request::get;
+use crate::errors::{Error, Result};
+use crate::request::{get, get_vec};
 use crate::{Client, QueryMeta, QueryOptions};
 
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct HealthCheck {
     pub Node: String,
     pub CheckID: String,
     pub Name: String,
-    pub Status: String,
+    pub Status: HealthStatus,
     pub Notes: String,
     pub Output: String,
     pub ServiceID: String,
     pub ServiceName: String,
     pub ServiceTags: Option<Vec<String>>,
+    /// The kind of check, e.g. `http`, `tcp` or `ttl`. Empty for externally registered checks.
+    pub Type: String,
+    pub Definition: HealthCheckDefinition,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Namespace: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Partition: String,
+    pub CreateIndex: u64,
+    pub ModifyIndex: u64,
+}
+
+/// The status of a check, or of a set of checks, see `HealthStatus::aggregate`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Passing,
+    Warning,
+    Critical,
+    /// The node or service is in maintenance mode. Checks themselves report maintenance as
+    /// `Critical`, so this only results from `aggregate`.
+    Maintenance,
+}
+
+impl HealthStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthStatus::Passing => "passing",
+            HealthStatus::Warning => "warning",
+            HealthStatus::Critical => "critical",
+            HealthStatus::Maintenance => "maintenance",
+        }
+    }
+
+    /// The overall status of `checks`: maintenance if a maintenance check is present,
+    /// otherwise the worst status, or passing if there are no checks.
+    pub fn aggregate(checks: &[HealthCheck]) -> HealthStatus {
+        let in_maintenance = checks.iter().any(|c| {
+            c.CheckID == NODE_MAINTENANCE_CHECK
+                || c.CheckID.starts_with(SERVICE_MAINTENANCE_CHECK_PREFIX)
+        });
+        if in_maintenance {
+            return HealthStatus::Maintenance;
+        }
+        let worst = |status| checks.iter().any(|c| c.Status == status);
+        if worst(HealthStatus::Critical) {
+            HealthStatus::Critical
+        } else if worst(HealthStatus::Warning) {
+            HealthStatus::Warning
+        } else {
+            HealthStatus::Passing
+        }
+    }
+}
+
+/// Consul registers new checks as critical until they have run.
+impl Default for HealthStatus {
+    fn default() -> HealthStatus {
+        HealthStatus::Critical
+    }
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// ID of the check added while a node is in maintenance mode.
+const NODE_MAINTENANCE_CHECK: &str = "_node_maintenance";
+/// Prefix of the ID of the check added while a service is in maintenance mode.
+const SERVICE_MAINTENANCE_CHECK_PREFIX: &str = "_service_maintenance:";
+
+/// How an HTTP or TCP check is run. Only used by checks registered through the catalog,
+/// e.g. for Consul ESM to run against external nodes. Durations are strings such as `"10s"`.
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct HealthCheckDefinition {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub HTTP: String,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub Header: HashMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Method: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Body: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub TLSServerName: String,
+    pub TLSSkipVerify: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub TCP: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Interval: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub Timeout: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub DeregisterCriticalServiceAfter: String,
 }
 
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct Node {
     pub ID: String,
     pub Node: String,
@@ -33,8 +130,8 @@ pub struct Node {
     pub ModifyIndex: u64,
 }
 
+#[derive(Clone, Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 #[serde(default)]
-#[derive(Eq, Default, PartialEq, Serialize, Deserialize, Debug)]
 pub struct ServiceEntry {
     pub Node: Node,
     pub Service: AgentService,
@@ -50,10 +147,44 @@ pub trait Health {
         passing_only: bool,
         options: Option<&QueryOptions>,
     ) -> Result<(Vec<ServiceEntry>, QueryMeta)>;
+    /// Instances of a service that can be reached through Connect, i.e. its proxies and
+    /// native Connect instances.
+    async fn connect(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        passing_only: bool,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMeta)>;
+    /// The ingress gateways exposing a service.
+    async fn ingress(
+        &self,
+        service: &str,
+        passing_only: bool,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMeta)>;
+    async fn node(
+        &self,
+        node: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<HealthCheck>, QueryMeta)>;
+    async fn checks(
+        &self,
+        service: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<HealthCheck>, QueryMeta)>;
+    /// Checks in `state`, or all checks for `None`. Maintenance isn't a state checks can be
+    /// in, so `Maintenance` is rejected.
+    async fn state(
+        &self,
+        state: Option<HealthStatus>,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<HealthCheck>, QueryMeta)>;
 }
 
 #[async_trait]
 impl Health for Client {
+    /// https://www.consul.io/api/health.html#list-nodes-for-service
     async fn service(
         &self,
         service: &str,
@@ -61,14 +192,82 @@ impl Health for Client {
         passing_only: bool,
         options: Option<&QueryOptions>,
     ) -> Result<(Vec<ServiceEntry>, QueryMeta)> {
-        let mut params = HashMap::new();
         let path = format!("/v1/health/service/{}", service);
-        if passing_only {
-            params.insert(String::from("passing"), String::from("1"));
-        }
-        if let Some(tag) = tag {
-            params.insert(String::from("tag"), tag.to_owned());
-        }
+        let params = entry_params(tag, passing_only);
         get(&path, &self.config, params, options).await
     }
+
+    /// https://www.consul.io/api/health.html#list-nodes-for-connect-capable-service
+    async fn connect(
+        &self,
+        service: &str,
+        tag: Option<&str>,
+        passing_only: bool,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMeta)> {
+        let path = format!("/v1/health/connect/{}", service);
+        let params = entry_params(tag, passing_only);
+        get_vec(&path, &self.config, params, options).await
+    }
+
+    /// https://www.consul.io/api/health.html#list-nodes-for-ingress-gateways-associated-to-a-service
+    async fn ingress(
+        &self,
+        service: &str,
+        passing_only: bool,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMeta)> {
+        let path = format!("/v1/health/ingress/{}", service);
+        let params = entry_params(None, passing_only);
+        get_vec(&path, &self.config, params, options).await
+    }
+
+    /// https://www.consul.io/api/health.html#list-checks-for-node
+    async fn node(
+        &self,
+        node: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<HealthCheck>, QueryMeta)> {
+        let path = format!("/v1/health/node/{}", node);
+        get_vec(&path, &self.config, HashMap::new(), options).await
+    }
+
+    /// https://www.consul.io/api/health.html#list-checks-for-service
+    async fn checks(
+        &self,
+        service: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<HealthCheck>, QueryMeta)> {
+        let path = format!("/v1/health/checks/{}", service);
+        get_vec(&path, &self.config, HashMap::new(), options).await
+    }
+
+    /// https://www.consul.io/api/health.html#list-checks-in-state
+    async fn state(
+        &self,
+        state: Option<HealthStatus>,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Vec<HealthCheck>, QueryMeta)> {
+        if state == Some(HealthStatus::Maintenance) {
+            return Err(Error::InvalidArgument(
+                "maintenance isn't a check state, checks in maintenance are critical",
+            ));
+        }
+        let path = format!(
+            "/v1/health/state/{}",
+            state.map_or("any", HealthStatus::as_str)
+        );
+        get_vec(&path, &self.config, HashMap::new(), options).await
+    }
+}
+
+fn entry_params(tag: Option<&str>, passing_only: bool) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    if passing_only {
+        params.insert(String::from("passing"), String::from("1"));
+    }
+    if let Some(tag) = tag {
+        params.insert(String::from("tag"), tag.to_owned());
+    }
+    params
 }
@@ -1,32 +1,136 @@
 use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cmp;
 use std::collections::HashMap;
+use std::str;
+use std::time::Duration;
+use tokio::time::delay_for;
 
 use crate::errors::Error;
 use crate::errors::Result;
-use crate::request::{delete, get, get_vec, put, Body};
-use crate::{Client, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+use crate::request::{delete, get_bytes, get_vec, put, Body};
+use crate::{Client, Config, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+
+const WATCH_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const WATCH_BACKOFF_MAX: Duration = Duration::from_secs(60);
 
-#[serde(default)]
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct KVPair {
     pub Key: String,
     pub CreateIndex: Option<u64>,
     pub ModifyIndex: Option<u64>,
     pub LockIndex: Option<u64>,
     pub Flags: Option<u64>,
-    pub Value: Option<String>,
+    /// Raw value. Consul transfers it base64 encoded, which is handled transparently.
+    #[serde(with = "base64_value")]
+    pub Value: Option<Vec<u8>>,
     pub Session: Option<String>,
 }
 
+impl KVPair {
+    /// The value as UTF-8 text.
+    pub fn value_str(&self) -> Result<Option<&str>> {
+        self.Value
+            .as_ref()
+            .map(|v| {
+                str::from_utf8(v).map_err(|e| Error::InvalidValue {
+                    key: self.Key.clone(),
+                    source: Box::new(e),
+                })
+            })
+            .transpose()
+    }
+
+    /// The value deserialized from JSON.
+    pub fn value_json<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        self.Value
+            .as_ref()
+            .map(|v| {
+                serde_json::from_slice(v).map_err(|e| Error::InvalidValue {
+                    key: self.Key.clone(),
+                    source: Box::new(e),
+                })
+            })
+            .transpose()
+    }
+}
+
+/// (De)serializes KV values to and from the base64 strings used by the HTTP API.
+pub(crate) mod base64_value {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(v) => serializer.serialize_str(&base64::encode(v)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|v| base64::decode(&v).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
 #[async_trait]
 pub trait KV {
     async fn acquire(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn cas(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
     async fn delete(&self, _: &str, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn delete_cas(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn delete_tree(&self, _: &str, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
     async fn get(&self, _: &str, _: Option<&QueryOptions>) -> Result<(Option<KVPair>, QueryMeta)>;
+    async fn get_raw(
+        &self,
+        _: &str,
+        _: Option<&QueryOptions>,
+    ) -> Result<(Option<Vec<u8>>, QueryMeta)>;
+    // The generic methods need `Self: Sized` so that `dyn KV` can still be used.
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        _: &str,
+        _: Option<&QueryOptions>,
+    ) -> Result<(Option<T>, QueryMeta)>
+    where
+        Self: Sized;
     async fn list(&self, _: &str, _: Option<&QueryOptions>) -> Result<(Vec<KVPair>, QueryMeta)>;
+    async fn list_json<T: DeserializeOwned>(
+        &self,
+        _: &str,
+        _: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, T>, QueryMeta)>
+    where
+        Self: Sized;
     async fn keys(&self, _: &str, _: Option<&str>, _: Option<&QueryOptions>) -> Result<(Vec<String>, QueryMeta)>;
     async fn put(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    async fn put_json<T: Serialize + Sync>(
+        &self,
+        _: &str,
+        _: &T,
+        _: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)>
+    where
+        Self: Sized;
     async fn release(&self, _: &KVPair, _: Option<&WriteOptions>) -> Result<(bool, WriteMeta)>;
+    fn watch(
+        &self,
+        _: &str,
+        _: Option<&QueryOptions>,
+    ) -> BoxStream<'static, Result<(Option<KVPair>, QueryMeta)>>;
+    fn watch_prefix(
+        &self,
+        _: &str,
+        _: Option<&QueryOptions>,
+    ) -> BoxStream<'static, Result<(Vec<KVPair>, QueryMeta)>>;
 }
 
 #[async_trait]
@@ -42,13 +146,34 @@ impl KV for Client {
             params.insert(String::from("acquire"), session.to_string());
             let path = format!("/v1/kv/{}", pair.Key);
 
-            let value = pair.Value.as_ref()
-                .map(|v| Body::AsText::<String>(v.to_string()));
+            let value = pair.Value.as_ref().map(|v| Body::AsBytes::<()>(v.clone()));
 
             put(&path, value, &self.config, params, o).await
         } else {
-            Err(Error::from("Session flag is required to acquire lock"))
+            Err(Error::InvalidArgument(
+                "Session flag is required to acquire lock",
+            ))
+        }
+    }
+
+    /// Writes the pair only if its `ModifyIndex` still matches the key's. An index of 0
+    /// only creates the key if it doesn't exist.
+    async fn cas(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
+        let mut params = HashMap::new();
+        if let Some(i) = pair.Flags {
+            if i != 0 {
+                params.insert(String::from("flags"), i.to_string());
+            }
         }
+        params.insert(
+            String::from("cas"),
+            pair.ModifyIndex.unwrap_or(0).to_string(),
+        );
+        let path = format!("/v1/kv/{}", pair.Key);
+
+        let value = pair.Value.as_ref().map(|v| Body::AsBytes::<()>(v.clone()));
+
+        put(&path, value, &self.config, params, o).await
     }
 
     async fn delete(&self, key: &str, options: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
@@ -56,14 +181,67 @@ impl KV for Client {
         delete(&path, &self.config, HashMap::new(), options).await
     }
 
+    /// Deletes the key only if its `ModifyIndex` still matches the pair's.
+    async fn delete_cas(
+        &self,
+        pair: &KVPair,
+        options: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)> {
+        let mut params = HashMap::new();
+        params.insert(
+            String::from("cas"),
+            pair.ModifyIndex.unwrap_or(0).to_string(),
+        );
+        let path = format!("/v1/kv/{}", pair.Key);
+        delete(&path, &self.config, params, options).await
+    }
+
+    /// Deletes every key under `prefix`.
+    async fn delete_tree(
+        &self,
+        prefix: &str,
+        options: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)> {
+        let mut params = HashMap::new();
+        params.insert(String::from("recurse"), String::from(""));
+        let path = format!("/v1/kv/{}", prefix);
+        delete(&path, &self.config, params, options).await
+    }
+
+    /// Returns `None` if the key doesn't exist.
     async fn get(
         &self,
         key: &str,
         options: Option<&QueryOptions>,
     ) -> Result<(Option<KVPair>, QueryMeta)> {
         let path = format!("/v1/kv/{}", key);
-        let x: Result<(Vec<KVPair>, QueryMeta)> = get(&path, &self.config, HashMap::new(), options).await;
-        x.map(|r| (r.0.first().cloned(), r.1))
+        let x: Result<(Vec<KVPair>, QueryMeta)> =
+            get_vec(&path, &self.config, HashMap::new(), options).await;
+        x.map(|r| (r.0.into_iter().next(), r.1))
+    }
+
+    /// Fetches only the value, without base64 encoding or metadata. Suited to large values.
+    async fn get_raw(
+        &self,
+        key: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Option<Vec<u8>>, QueryMeta)> {
+        let mut params = HashMap::new();
+        params.insert(String::from("raw"), String::from(""));
+        let path = format!("/v1/kv/{}", key);
+        get_bytes(&path, &self.config, params, options).await
+    }
+
+    /// Fetches the key and deserializes its value from JSON.
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        options: Option<&QueryOptions>,
+    ) -> Result<(Option<T>, QueryMeta)> {
+        match self.get(key, options).await? {
+            (Some(pair), meta) => pair.value_json().map(|v| (v, meta)),
+            (None, meta) => Ok((None, meta)),
+        }
     }
 
     async fn list(&self, prefix: &str, o: Option<&QueryOptions>) -> Result<(Vec<KVPair>, QueryMeta)> {
@@ -73,6 +251,23 @@ impl KV for Client {
         get_vec(&path, &self.config, params, o).await
     }
 
+    /// Deserializes every value under `prefix` from JSON, keyed by the full key name.
+    /// Keys without a value, such as folders, are skipped.
+    async fn list_json<T: DeserializeOwned>(
+        &self,
+        prefix: &str,
+        o: Option<&QueryOptions>,
+    ) -> Result<(HashMap<String, T>, QueryMeta)> {
+        let (pairs, meta) = self.list(prefix, o).await?;
+        let mut values = HashMap::new();
+        for pair in pairs {
+            if let Some(value) = pair.value_json()? {
+                values.insert(pair.Key, value);
+            }
+        }
+        Ok((values, meta))
+    }
+
     async fn keys(&self, prefix: &str, separator: Option<&str>, o: Option<&QueryOptions>) -> Result<(Vec<String>, QueryMeta)> {
         let mut params = HashMap::new();
         params.insert(String::from("keys"), String::from(""));
@@ -93,12 +288,29 @@ impl KV for Client {
         }
         let path = format!("/v1/kv/{}", pair.Key);
 
-        let value = pair.Value.as_ref()
-            .map(|v| Body::AsText::<String>(v.to_string()));
+        let value = pair.Value.as_ref().map(|v| Body::AsBytes::<()>(v.clone()));
 
         put(&path, value, &self.config, params, o).await
     }
 
+    /// Serializes `value` to JSON and stores it in `key`.
+    async fn put_json<T: Serialize + Sync>(
+        &self,
+        key: &str,
+        value: &T,
+        o: Option<&WriteOptions>,
+    ) -> Result<(bool, WriteMeta)> {
+        let path = format!("/v1/kv/{}", key);
+        put(
+            &path,
+            Some(Body::AsJson(value)),
+            &self.config,
+            HashMap::new(),
+            o,
+        )
+        .await
+    }
+
     async fn release(&self, pair: &KVPair, o: Option<&WriteOptions>) -> Result<(bool, WriteMeta)> {
         let mut params = HashMap::new();
         if let Some(i) = pair.Flags {
@@ -110,12 +322,121 @@ impl KV for Client {
             params.insert(String::from("release"), session.to_owned());
             let path = format!("/v1/kv/{}", pair.Key);
 
-            let value = pair.Value.as_ref()
-                .map(|v| Body::AsText::<String>(v.to_string()));
+            let value = pair.Value.as_ref().map(|v| Body::AsBytes::<()>(v.clone()));
 
             put(&path, value, &self.config, params, o).await
         } else {
-            Err(Error::from("Session flag is required to release a lock"))
+            Err(Error::InvalidArgument(
+                "Session flag is required to release a lock",
+            ))
+        }
+    }
+
+    /// Streams the value of `key` every time its `ModifyIndex` changes, using blocking queries.
+    /// A deleted or missing key is yielded as `None`.
+    fn watch(
+        &self,
+        key: &str,
+        o: Option<&QueryOptions>,
+    ) -> BoxStream<'static, Result<(Option<KVPair>, QueryMeta)>> {
+        let path = format!("/v1/kv/{}", key);
+        KVWatch::new(self.config.clone(), path, HashMap::new(), o)
+            .into_stream()
+            .map(|r| r.map(|(pairs, meta)| (pairs.into_iter().next(), meta)))
+            .boxed()
+    }
+
+    /// Streams every pair under `prefix` each time a key is added, changed or removed.
+    fn watch_prefix(
+        &self,
+        prefix: &str,
+        o: Option<&QueryOptions>,
+    ) -> BoxStream<'static, Result<(Vec<KVPair>, QueryMeta)>> {
+        let mut params = HashMap::new();
+        params.insert(String::from("recurse"), String::from(""));
+        let path = format!("/v1/kv/{}", prefix);
+        KVWatch::new(self.config.clone(), path, params, o)
+            .into_stream()
+            .boxed()
+    }
+}
+
+/// State of a blocking-query loop over a KV path.
+///
+/// See https://www.consul.io/api-docs/features/blocking for the index handling rules.
+struct KVWatch {
+    config: Config,
+    path: String,
+    params: HashMap<String, String>,
+    options: QueryOptions,
+    seen: Option<Vec<(String, Option<u64>)>>,
+    failures: u32,
+}
+
+impl KVWatch {
+    fn new(
+        config: Config,
+        path: String,
+        params: HashMap<String, String>,
+        o: Option<&QueryOptions>,
+    ) -> KVWatch {
+        KVWatch {
+            config,
+            path,
+            params,
+            options: o.cloned().unwrap_or_default(),
+            seen: None,
+            failures: 0,
         }
     }
+
+    fn into_stream(self) -> impl futures::Stream<Item = Result<(Vec<KVPair>, QueryMeta)>> {
+        stream::unfold(self, |watch| watch.next())
+    }
+
+    async fn next(mut self) -> Option<(Result<(Vec<KVPair>, QueryMeta)>, KVWatch)> {
+        loop {
+            if self.failures > 0 {
+                delay_for(watch_backoff(self.failures)).await;
+            }
+
+            let (pairs, meta) = match get_vec::<KVPair>(
+                &self.path,
+                &self.config,
+                self.params.clone(),
+                Some(&self.options),
+            )
+            .await
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    self.failures = self.failures.saturating_add(1);
+                    return Some((Err(e), self));
+                }
+            };
+            self.failures = 0;
+
+            // Start over if the index went backwards, and never block on an index of 0
+            // since Consul would answer immediately.
+            let previous = self.options.wait_index.unwrap_or(0);
+            self.options.wait_index = match meta.last_index {
+                Some(index) if index >= previous => Some(cmp::max(index, 1)),
+                _ => None,
+            };
+
+            let fingerprint: Vec<(String, Option<u64>)> = pairs
+                .iter()
+                .map(|p| (p.Key.clone(), p.ModifyIndex))
+                .collect();
+            if self.seen.as_ref() != Some(&fingerprint) {
+                self.seen = Some(fingerprint);
+                return Some((Ok((pairs, meta)), self));
+            }
+        }
+    }
+}
+
+fn watch_backoff(failures: u32) -> Duration {
+    let factor = 1u32 << cmp::min(failures.saturating_sub(1), 6);
+    cmp::min(WATCH_BACKOFF_MIN * factor, WATCH_BACKOFF_MAX)
 }
@@ -1,29 +1,40 @@
 #![allow(non_snake_case)]
 #![allow(unused_doc_comments)]
 
-#[macro_use]
-extern crate error_chain;
 #[macro_use]
 extern crate serde_derive;
 
 pub mod agent;
 pub mod catalog;
 pub mod connect_ca;
+pub mod election;
 pub mod errors;
+pub mod filter;
 pub mod health;
 pub mod kv;
+pub mod lock;
+pub mod semaphore;
 pub mod session;
+pub mod txn;
 
+mod pkcs8;
 mod request;
+mod transport;
 
+use std::collections::HashMap;
 use std::env;
-
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use rand::Rng;
 use reqwest::Client as HttpClient;
 use reqwest::ClientBuilder;
+use reqwest::StatusCode;
+use url::Url;
 
-use errors::{Result, ResultExt};
+use errors::{Error, Result};
+use transport::ServerNameTls;
 
 #[derive(Clone, Debug)]
 pub struct Client {
@@ -38,68 +49,563 @@ impl Client {
 
 #[derive(Clone, Debug)]
 pub struct Config {
+    /// Base URL of the agent, e.g. `http://127.0.0.1:8500`, or `unix:///path/to/consul.sock`
+    /// for an agent serving HTTP on a unix socket.
     pub address: String,
     pub datacenter: Option<String>,
     pub http_client: HttpClient,
     pub token: Option<String>,
+    /// Credentials for agents behind a proxy requiring HTTP basic authentication.
+    pub http_auth: Option<HttpBasicAuth>,
+    /// Default namespace (Consul Enterprise), overridable per request.
+    pub namespace: Option<String>,
+    /// Default admin partition (Consul Enterprise), overridable per request.
+    pub partition: Option<String>,
+    /// Wait time of blocking queries whose `QueryOptions` don't set one.
     pub wait_time: Option<Duration>,
+    /// Limit on a whole request, see `ConfigBuilder::timeout`. Already part of
+    /// `http_client`, and applied separately to requests over a unix socket.
+    pub timeout: Option<Duration>,
+    pub retry_policy: RetryPolicy,
+    /// Set when `TlsConfig::server_name` is verified, for requests to a host other than that
+    /// name, since `http_client` can only verify the certificate against the host.
+    server_name_tls: Option<ServerNameTls>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HttpBasicAuth {
+    pub username: String,
+    pub password: Option<String>,
 }
 
 impl Config {
     pub fn new() -> Result<Config> {
-        ClientBuilder::new()
-            .build()
-            .chain_err(|| "Failed to build reqwest client")
-            .map(|client| Config {
-                address: String::from("http://localhost:8500"),
-                datacenter: None,
-                http_client: client,
-                token: None,
-                wait_time: None,
-            })
+        ConfigBuilder::new().build()
     }
 
+    /// Reads the environment variables listed on `ConfigBuilder::from_env`.
     pub fn new_from_env() -> Result<Config> {
-        let consul_addr = match env::var("CONSUL_HTTP_ADDR") {
-            Ok(val) => {
-                if val.starts_with("http") {
+        ConfigBuilder::from_env()?.build()
+    }
+
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// Rebuilds `http_client` with `tls`, for agents serving HTTPS. The scheme of `address`
+    /// still decides whether TLS is used. Other client settings made through
+    /// `ConfigBuilder` are reset, so prefer `ConfigBuilder::tls`.
+    pub fn with_tls(mut self, tls: &TlsConfig) -> Result<Config> {
+        let connector = tls.connector()?;
+        self.server_name_tls = tls.server_name_tls(&connector);
+        self.http_client = ClientBuilder::new()
+            .use_preconfigured_tls(connector)
+            .build()
+            .map_err(Error::Client)?;
+        Ok(self)
+    }
+}
+
+/// Builds a `Config`, checking the settings up front.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// let config = consul::Config::builder()
+///     .address("https://consul.service.consul:8501")
+///     .token("secret")
+///     .wait_time(Duration::from_secs(30))
+///     .build()?;
+/// # Ok::<(), consul::errors::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ConfigBuilder {
+    address: Option<String>,
+    datacenter: Option<String>,
+    token: Option<String>,
+    http_auth: Option<HttpBasicAuth>,
+    namespace: Option<String>,
+    partition: Option<String>,
+    wait_time: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    tls: Option<TlsConfig>,
+    user_agent: Option<String>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Starts from the same environment variables as the Go client's `api.DefaultConfig`:
+    ///
+    /// * `CONSUL_HTTP_ADDR`, which may be a `unix://` socket path, and `CONSUL_HTTP_SSL`
+    ///   picking the scheme when the address has none.
+    /// * `CONSUL_HTTP_TOKEN_FILE`, whose contents take precedence over `CONSUL_HTTP_TOKEN`.
+    /// * `CONSUL_HTTP_AUTH`, as `username[:password]`.
+    /// * `CONSUL_NAMESPACE`, `CONSUL_PARTITION` and `CONSUL_DATACENTER`.
+    /// * The TLS variables listed on `TlsConfig::from_env`.
+    ///
+    /// Empty variables are treated as unset.
+    pub fn from_env() -> Result<ConfigBuilder> {
+        let scheme = match env_bool("CONSUL_HTTP_SSL")? {
+            Some(true) => "https",
+            _ => "http",
+        };
+        let consul_addr = match env_var("CONSUL_HTTP_ADDR") {
+            Some(val) => {
+                if val.starts_with("http") || val.starts_with("unix://") {
                     val
                 } else {
-                    format!("http://{}", val)
+                    format!("{}://{}", scheme, val)
                 }
             }
-            Err(_e) => String::from("http://127.0.0.1:8500"),
+            None => format!("{}://127.0.0.1:8500", scheme),
         };
-        let consul_token = env::var("CONSUL_HTTP_TOKEN").ok();
-        ClientBuilder::new()
-            .build()
-            .chain_err(|| "Failed to build reqwest client")
-            .map(|client| Config {
-                address: consul_addr,
-                datacenter: None,
-                http_client: client,
-                token: consul_token,
-                wait_time: None,
-            })
+        let consul_token = match env_var("CONSUL_HTTP_TOKEN_FILE") {
+            Some(file) => read_token_file(Path::new(&file))?,
+            None => None,
+        }
+        .or_else(|| env_var("CONSUL_HTTP_TOKEN"));
+        let http_auth = env_var("CONSUL_HTTP_AUTH").map(|auth| {
+            let mut parts = auth.splitn(2, ':');
+            HttpBasicAuth {
+                username: parts.next().unwrap_or_default().to_owned(),
+                password: parts.next().map(String::from),
+            }
+        });
+        Ok(ConfigBuilder {
+            address: Some(consul_addr),
+            datacenter: env_var("CONSUL_DATACENTER"),
+            token: consul_token,
+            http_auth,
+            namespace: env_var("CONSUL_NAMESPACE"),
+            partition: env_var("CONSUL_PARTITION"),
+            tls: Some(TlsConfig::from_env()?),
+            ..Default::default()
+        })
+    }
+
+    /// `http://` or `https://` URL of the agent, or `unix://` followed by a socket path.
+    /// Defaults to `http://localhost:8500`.
+    pub fn address<S: Into<String>>(mut self, address: S) -> ConfigBuilder {
+        self.address = Some(address.into());
+        self
+    }
+
+    pub fn datacenter<S: Into<String>>(mut self, datacenter: S) -> ConfigBuilder {
+        self.datacenter = Some(datacenter.into());
+        self
+    }
+
+    pub fn token<S: Into<String>>(mut self, token: S) -> ConfigBuilder {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn http_auth(mut self, auth: HttpBasicAuth) -> ConfigBuilder {
+        self.http_auth = Some(auth);
+        self
+    }
+
+    pub fn namespace<S: Into<String>>(mut self, namespace: S) -> ConfigBuilder {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    pub fn partition<S: Into<String>>(mut self, partition: S) -> ConfigBuilder {
+        self.partition = Some(partition.into());
+        self
+    }
+
+    /// How long blocking queries wait for a change when their `QueryOptions` don't say.
+    pub fn wait_time(mut self, wait_time: Duration) -> ConfigBuilder {
+        self.wait_time = Some(wait_time);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> ConfigBuilder {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit on a whole request. Must exceed the wait time, or blocking queries time out.
+    pub fn timeout(mut self, timeout: Duration) -> ConfigBuilder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> ConfigBuilder {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> ConfigBuilder {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> ConfigBuilder {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn build(self) -> Result<Config> {
+        let address = self
+            .address
+            .unwrap_or_else(|| String::from("http://localhost:8500"));
+        validate_address(&address)?;
+        if let (Some(timeout), Some(wait_time)) = (self.timeout, self.wait_time) {
+            if timeout <= wait_time {
+                return Err(Error::InvalidArgument(
+                    "timeout must exceed the wait time of blocking queries",
+                ));
+            }
+        }
+
+        let mut builder = ClientBuilder::new();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(ref user_agent) = self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        let mut server_name_tls = None;
+        if let Some(ref tls) = self.tls {
+            let connector = tls.connector()?;
+            server_name_tls = tls.server_name_tls(&connector);
+            builder = builder.use_preconfigured_tls(connector);
+        }
+
+        Ok(Config {
+            address,
+            datacenter: self.datacenter,
+            http_client: builder.build().map_err(Error::Client)?,
+            token: self.token,
+            http_auth: self.http_auth,
+            namespace: self.namespace,
+            partition: self.partition,
+            wait_time: self.wait_time,
+            timeout: self.timeout,
+            retry_policy: self.retry_policy.unwrap_or_default(),
+            server_name_tls,
+        })
+    }
+}
+
+/// Checks that `address` is usable as a base URL.
+fn validate_address(address: &str) -> Result<()> {
+    let invalid = |reason: String| Error::InvalidAddress {
+        address: address.to_owned(),
+        reason,
+    };
+    if let Some(socket) = address.strip_prefix("unix://") {
+        if socket.is_empty() {
+            return Err(invalid(String::from("missing socket path")));
+        }
+        return Ok(());
+    }
+    let url = Url::parse(address).map_err(|e| invalid(e.to_string()))?;
+    match url.scheme() {
+        "http" | "https" => {}
+        scheme => return Err(invalid(format!("unsupported scheme {}", scheme))),
+    }
+    match url.host_str() {
+        Some(_) => Ok(()),
+        None => Err(invalid(String::from("missing host"))),
+    }
+}
+
+/// TLS settings for agents serving HTTPS, mirroring `api.TLSConfig` from the Go client.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// PEM file of CA certificates the agent's certificate is verified against, instead of
+    /// the system roots.
+    pub ca_file: Option<PathBuf>,
+    /// Directory of PEM files of CA certificates, used alongside `ca_file`.
+    pub ca_path: Option<PathBuf>,
+    /// PEM client certificate, for agents with `verify_incoming` set.
+    pub cert_file: Option<PathBuf>,
+    /// PEM key of `cert_file`, in PKCS#8, SEC1 (`EC PRIVATE KEY`, as written by
+    /// `consul tls cert create`) or PKCS#1 (`RSA PRIVATE KEY`) format.
+    pub key_file: Option<PathBuf>,
+    /// Name the agent's certificate is verified against instead of the address host, e.g.
+    /// `server.dc1.consul` for an agent reached at `https://127.0.0.1:8501`.
+    pub server_name: Option<String>,
+    /// Accepts any certificate from the agent.
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsConfig {
+    /// Reads `CONSUL_CACERT`, `CONSUL_CAPATH`, `CONSUL_CLIENT_CERT`, `CONSUL_CLIENT_KEY`,
+    /// `CONSUL_TLS_SERVER_NAME` and `CONSUL_HTTP_SSL_VERIFY`.
+    pub fn from_env() -> Result<TlsConfig> {
+        let path = |name| {
+            env::var_os(name)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+        Ok(TlsConfig {
+            ca_file: path("CONSUL_CACERT"),
+            ca_path: path("CONSUL_CAPATH"),
+            cert_file: path("CONSUL_CLIENT_CERT"),
+            key_file: path("CONSUL_CLIENT_KEY"),
+            server_name: env::var("CONSUL_TLS_SERVER_NAME")
+                .ok()
+                .filter(|v| !v.is_empty()),
+            insecure_skip_verify: env_bool("CONSUL_HTTP_SSL_VERIFY")? == Some(false),
+        })
+    }
+
+    fn connector(&self) -> Result<native_tls::TlsConnector> {
+        let mut builder = native_tls::TlsConnector::builder();
+
+        if self.ca_file.is_some() || self.ca_path.is_some() {
+            builder.disable_built_in_roots(true);
+        }
+        if let Some(ref file) = self.ca_file {
+            for cert in load_certificates(file)? {
+                builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(ref dir) = self.ca_path {
+            let entries = fs::read_dir(dir).map_err(|e| Error::tls_material(dir, e))?;
+            for entry in entries {
+                let file = entry.map_err(|e| Error::tls_material(dir, e))?.path();
+                if !file.is_file() {
+                    continue;
+                }
+                for cert in load_certificates(&file)? {
+                    builder.add_root_certificate(cert);
+                }
+            }
+        }
+
+        match (&self.cert_file, &self.key_file) {
+            (Some(cert), Some(key)) => {
+                let cert_pem = fs::read(cert).map_err(|e| Error::tls_material(cert, e))?;
+                let key_pem = fs::read_to_string(key).map_err(|e| Error::tls_material(key, e))?;
+                let key_pem = pkcs8::to_pkcs8(&key_pem).map_err(|e| Error::tls_material(key, e))?;
+                let identity = native_tls::Identity::from_pkcs8(&cert_pem, key_pem.as_bytes())
+                    .map_err(|e| Error::tls_material(key, e))?;
+                builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => {
+                return Err(Error::InvalidArgument(
+                    "TLS client certificate and key must be set together",
+                ))
+            }
+        }
+
+        builder.danger_accept_invalid_certs(self.insecure_skip_verify);
+        builder.build().map_err(Error::Tls)
+    }
+
+    /// A skipped verification doesn't look at the name, so it's only kept otherwise.
+    fn server_name_tls(&self, connector: &native_tls::TlsConnector) -> Option<ServerNameTls> {
+        match self.server_name {
+            Some(ref name) if !self.insecure_skip_verify => Some(ServerNameTls {
+                connector: connector.clone().into(),
+                server_name: name.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Loads every certificate of a PEM bundle.
+fn load_certificates(path: &Path) -> Result<Vec<native_tls::Certificate>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let pem = fs::read_to_string(path).map_err(|e| Error::tls_material(path, e))?;
+    let mut certs = Vec::new();
+    let mut rest = pem.as_str();
+    while let Some(start) = rest.find(BEGIN) {
+        let block = &rest[start..];
+        let end = match block.find(END) {
+            Some(end) => end + END.len(),
+            None => break,
+        };
+        let cert = native_tls::Certificate::from_pem(&block.as_bytes()[..end])
+            .map_err(|e| Error::tls_material(path, e))?;
+        certs.push(cert);
+        rest = &block[end..];
+    }
+    if certs.is_empty() {
+        return Err(Error::tls_material(path, "no PEM certificates found"));
+    }
+    Ok(certs)
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// Reads a token file, ignoring surrounding whitespace. An empty file holds no token.
+fn read_token_file(path: &Path) -> Result<Option<String>> {
+    let token = fs::read_to_string(path).map_err(|e| Error::TokenFile {
+        path: path.to_owned(),
+        source: e,
+    })?;
+    let token = token.trim();
+    Ok(if token.is_empty() {
+        None
+    } else {
+        Some(token.to_owned())
+    })
+}
+
+/// Parses a boolean environment variable the way Go's `strconv.ParseBool` does.
+fn env_bool(name: &'static str) -> Result<Option<bool>> {
+    let value = match env::var(name) {
+        Ok(ref value) if value.is_empty() => return Ok(None),
+        Ok(value) => value,
+        Err(_) => return Ok(None),
+    };
+    match value.as_str() {
+        "1" | "t" | "T" | "true" | "TRUE" | "True" => Ok(Some(true)),
+        "0" | "f" | "F" | "false" | "FALSE" | "False" => Ok(Some(false)),
+        _ => Err(Error::InvalidEnvVar { name, value }),
+    }
+}
+
+/// How requests are retried after transient failures.
+///
+/// Reads, deletes and check-and-set writes are retried on a retryable status, a timeout or a
+/// dropped connection. Other writes are only retried when the connection couldn't be
+/// established, since Consul may already have applied them otherwise.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub retryable_statuses: Vec<StatusCode>,
+}
+
+impl RetryPolicy {
+    /// A policy making a single attempt.
+    pub fn disabled() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before the attempt following `attempt`: exponential backoff with full jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let ceiling = self
+            .initial_backoff
+            .checked_mul(1 << exponent)
+            .map_or(self.max_backoff, |d| d.min(self.max_backoff));
+        ceiling.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            retryable_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
     }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct QueryOptions {
     pub datacenter: Option<String>,
+    pub namespace: Option<String>,
+    pub partition: Option<String>,
     pub wait_index: Option<u64>,
     pub wait_time: Option<Duration>,
+    /// A filter expression applied to the results, see `filter::Filter`.
+    ///
+    /// See https://www.consul.io/api/features/filtering.html
+    pub filter: Option<String>,
+    /// Sorts nodes by round trip time from this node, or from the local agent for `_agent`.
+    pub near: Option<String>,
+    /// Only returns nodes with all of these meta pairs.
+    pub node_meta: HashMap<String, String>,
+    /// Reads through the leader after confirming its leadership, at the cost of an extra
+    /// round trip.
+    pub require_consistent: bool,
+    /// Lets any server answer, possibly with stale results. Can't be combined with
+    /// `require_consistent`.
+    pub allow_stale: bool,
+    /// Answers from the agent's cache, on endpoints that support it.
+    ///
+    /// See https://www.consul.io/api/features/caching.html
+    pub use_cache: bool,
+    /// With `use_cache`, the maximum age of a cached result before it is fetched again.
+    pub max_age: Option<Duration>,
+}
+
+impl QueryOptions {
+    /// The consistency mode requested by `require_consistent` and `allow_stale`.
+    pub fn consistency(&self) -> ConsistencyMode {
+        if self.require_consistent {
+            ConsistencyMode::Consistent
+        } else if self.allow_stale {
+            ConsistencyMode::Stale
+        } else {
+            ConsistencyMode::Default
+        }
+    }
+}
+
+/// How up to date the results of a read have to be.
+///
+/// See https://www.consul.io/api/features/consistency.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConsistencyMode {
+    /// Served by the leader, which may briefly be stale after losing leadership.
+    Default,
+    Consistent,
+    Stale,
 }
 
 #[derive(Clone, Debug)]
 pub struct QueryMeta {
     pub last_index: Option<u64>,
     pub request_time: Duration,
+    /// Whether the answering server knew of a leader. Results may be stale if it didn't.
+    pub known_leader: bool,
+    /// Time since the answering server last heard from the leader. Only non-zero for stale
+    /// reads, so it can be used to reject results that are too old.
+    pub last_contact: Duration,
+    /// The consistency mode the servers applied, which can differ from the requested one,
+    /// e.g. when the agent's `discovery_max_stale` turns default reads into stale ones.
+    pub consistency: Option<ConsistencyMode>,
+    /// Whether the agent cache answered, for requests with `use_cache`.
+    pub cache_hit: bool,
+    /// How long ago a cached response was fetched from the servers.
+    pub cache_age: Option<Duration>,
+    /// `allow` or `deny`, the ACL policy applied when no rule matches.
+    pub default_acl_policy: Option<String>,
+    /// Whether results were left out because the token isn't allowed to read them.
+    pub results_filtered_by_acls: bool,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct WriteOptions {
     pub datacenter: Option<String>,
+    pub namespace: Option<String>,
+    pub partition: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,408 @@
+use futures::channel::oneshot;
+use futures::future::{self, AbortHandle, Abortable, FutureExt, Shared};
+use futures::{Future, StreamExt};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::time::delay_for;
+
+use crate::errors::{Error, Result};
+use crate::kv::{KVPair, KV};
+use crate::session::{renew_periodic, Session, SessionEntry};
+use crate::{Client, QueryOptions};
+
+/// `Flags` value marking a key as a lock. Shared with the Go client so both can contend.
+pub const LOCK_FLAG_VALUE: u64 = 0x2ddc_cbc0_58a5_0c18;
+
+pub const DEFAULT_LOCK_SESSION_NAME: &str = "Consul API Lock";
+pub const DEFAULT_LOCK_SESSION_TTL: Duration = Duration::from_secs(15);
+pub const DEFAULT_LOCK_WAIT_TIME: Duration = Duration::from_secs(15);
+pub const DEFAULT_LOCK_RETRY_TIME: Duration = Duration::from_secs(5);
+
+/// Settings for a `Lock`, mirroring `api.LockOptions` from the Go client.
+#[derive(Clone, Debug)]
+pub struct LockOptions {
+    pub key: String,
+    pub value: Option<Vec<u8>>,
+    /// Existing session to lock with. When unset a session is created, renewed in the
+    /// background and destroyed on release.
+    pub session: Option<String>,
+    pub session_name: String,
+    pub session_ttl: Duration,
+    /// Lock delay of the created session. Consul uses 15s when unset.
+    pub lock_delay: Option<Duration>,
+    /// How long each blocking query waits while someone else holds the lock.
+    pub lock_wait_time: Duration,
+    /// How long to wait before retrying when the lock delay blocks acquisition.
+    pub lock_retry_time: Duration,
+    /// Number of failed queries tolerated while monitoring a held lock.
+    pub monitor_retries: u32,
+}
+
+impl LockOptions {
+    pub fn new(key: &str) -> LockOptions {
+        LockOptions {
+            key: key.to_owned(),
+            value: None,
+            session: None,
+            session_name: String::from(DEFAULT_LOCK_SESSION_NAME),
+            session_ttl: DEFAULT_LOCK_SESSION_TTL,
+            lock_delay: None,
+            lock_wait_time: DEFAULT_LOCK_WAIT_TIME,
+            lock_retry_time: DEFAULT_LOCK_RETRY_TIME,
+            monitor_retries: 0,
+        }
+    }
+}
+
+/// A distributed mutex on a single KV key.
+///
+/// See https://www.consul.io/docs/guides/leader-election.html for the underlying protocol.
+#[derive(Clone, Debug)]
+pub struct Lock {
+    client: Client,
+    options: LockOptions,
+}
+
+impl Lock {
+    pub fn new(client: &Client, options: LockOptions) -> Lock {
+        Lock {
+            client: client.clone(),
+            options,
+        }
+    }
+
+    /// Waits until the lock is held.
+    pub async fn acquire(&self) -> Result<LockGuard> {
+        self.lock(false).await?.ok_or_else(|| Error::NotAcquired {
+            key: self.options.key.clone(),
+        })
+    }
+
+    /// Makes a single attempt, returning `None` if somebody else holds the lock.
+    pub async fn try_acquire(&self) -> Result<Option<LockGuard>> {
+        self.lock(true).await
+    }
+
+    async fn lock(&self, try_once: bool) -> Result<Option<LockGuard>> {
+        let owned = match self.options.session {
+            Some(_) => None,
+            None => Some(
+                OwnedSession::create(
+                    &self.client,
+                    &self.options.session_name,
+                    self.options.session_ttl,
+                    self.options.lock_delay,
+                    "release",
+                )
+                .await?,
+            ),
+        };
+        let session = match owned {
+            Some(ref s) => s.id.clone(),
+            None => self.options.session.clone().unwrap_or_default(),
+        };
+
+        let mut query = QueryOptions {
+            wait_time: Some(self.options.lock_wait_time),
+            ..Default::default()
+        };
+        loop {
+            let (pair, meta) = self.client.get(&self.options.key, Some(&query)).await?;
+            if let Some(pair) = pair {
+                if pair.Flags != Some(LOCK_FLAG_VALUE) {
+                    return Err(Error::Conflict {
+                        key: self.options.key.clone(),
+                        kind: "lock",
+                    });
+                }
+                match pair.Session {
+                    Some(ref holder) if *holder == session => {
+                        return Ok(Some(LockGuard::new(
+                            &self.client,
+                            &self.options,
+                            pair,
+                            owned,
+                        )));
+                    }
+                    Some(_) => {
+                        if try_once {
+                            return Ok(None);
+                        }
+                        query.wait_index = meta.last_index;
+                        continue;
+                    }
+                    None => {}
+                }
+            }
+
+            let entry = lock_entry(&self.options, &session);
+            let (locked, _) = self.client.acquire(&entry, None).await?;
+            query.wait_index = None;
+            if locked {
+                // Read the key back on the next pass to pick up the new LockIndex.
+                continue;
+            }
+            if try_once {
+                return Ok(None);
+            }
+
+            // Either another contender won the race, or the lock delay of the previous
+            // holder's session is still in effect.
+            let (pair, meta) = self.client.get(&self.options.key, None).await?;
+            if pair.and_then(|p| p.Session).is_some() {
+                query.wait_index = meta.last_index;
+            } else {
+                delay_for(self.options.lock_retry_time).await;
+            }
+        }
+    }
+}
+
+/// Proof of holding a `Lock`. The lock is released when the guard is dropped.
+#[derive(Debug)]
+pub struct LockGuard {
+    client: Client,
+    pair: KVPair,
+    session: Option<OwnedSession>,
+    monitor: Monitor,
+    released: bool,
+}
+
+impl LockGuard {
+    fn new(
+        client: &Client,
+        options: &LockOptions,
+        pair: KVPair,
+        session: Option<OwnedSession>,
+    ) -> LockGuard {
+        let watch = monitor_lock(
+            client.clone(),
+            options.key.clone(),
+            pair.Session.clone().unwrap_or_default(),
+            options.lock_wait_time,
+            options.monitor_retries,
+        );
+        LockGuard {
+            client: client.clone(),
+            monitor: Monitor::spawn(client, session.as_ref(), watch),
+            pair,
+            session,
+            released: false,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.pair.Key
+    }
+
+    pub fn session(&self) -> &str {
+        self.pair.Session.as_deref().unwrap_or_default()
+    }
+
+    /// The key's `LockIndex` at acquisition, usable as a fencing token.
+    pub fn lock_index(&self) -> Option<u64> {
+        self.pair.LockIndex
+    }
+
+    /// Resolves once the lock is no longer held, e.g. because the session was invalidated
+    /// or the key was taken over or deleted.
+    pub fn lost(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.monitor.lost()
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.monitor.is_lost()
+    }
+
+    /// Releases the lock and destroys the session if the lock created it.
+    pub async fn release(mut self) -> Result<()> {
+        self.released = true;
+        self.monitor.stop();
+        release(&self.client, &self.pair, self.session.take()).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.monitor.stop();
+        let client = self.client.clone();
+        let pair = self.pair.clone();
+        let session = self.session.take();
+        spawn(async move {
+            let _ = release(&client, &pair, session).await;
+        });
+    }
+}
+
+async fn release(client: &Client, pair: &KVPair, session: Option<OwnedSession>) -> Result<()> {
+    let entry = KVPair {
+        LockIndex: None,
+        ..pair.clone()
+    };
+    let released = client.release(&entry, None).await.map(|_| ());
+    match session {
+        Some(s) => released.and(s.destroy().await),
+        None => released,
+    }
+}
+
+fn lock_entry(options: &LockOptions, session: &str) -> KVPair {
+    KVPair {
+        Key: options.key.clone(),
+        Value: options.value.clone(),
+        Flags: Some(LOCK_FLAG_VALUE),
+        Session: Some(session.to_owned()),
+        ..Default::default()
+    }
+}
+
+async fn monitor_lock(
+    client: Client,
+    key: String,
+    session: String,
+    wait_time: Duration,
+    retries: u32,
+) {
+    let options = QueryOptions {
+        wait_time: Some(wait_time),
+        ..Default::default()
+    };
+    let mut watch = client.watch(&key, Some(&options));
+    let mut failures = 0;
+    while let Some(result) = watch.next().await {
+        match result {
+            Ok((Some(ref pair), _)) if pair.Session.as_deref() == Some(session.as_str()) => {
+                failures = 0;
+            }
+            Ok(_) => return,
+            Err(_) => {
+                failures += 1;
+                if failures > retries {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Background task keeping a held primitive alive: renews its session, if owned, while
+/// running `watch`, and signals loss when either of them stops.
+#[derive(Debug)]
+pub(crate) struct Monitor {
+    lost: Shared<oneshot::Receiver<()>>,
+    abort: AbortHandle,
+}
+
+impl Monitor {
+    pub(crate) fn spawn<F>(client: &Client, session: Option<&OwnedSession>, watch: F) -> Monitor
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let (abort, registration) = AbortHandle::new_pair();
+        let renew = match session {
+            Some(s) => renew_periodic(client.clone(), s.id.clone(), s.ttl)
+                .map(|_| ())
+                .boxed(),
+            None => future::pending().boxed(),
+        };
+        spawn(Abortable::new(
+            async move {
+                future::select(renew, watch.boxed()).await;
+                let _ = tx.send(());
+            },
+            registration,
+        ));
+        Monitor {
+            lost: rx.shared(),
+            abort,
+        }
+    }
+
+    pub(crate) fn lost(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.lost.clone().map(|_| ())
+    }
+
+    pub(crate) fn is_lost(&self) -> bool {
+        self.lost.peek().is_some()
+    }
+
+    pub(crate) fn stop(&self) {
+        self.abort.abort();
+    }
+}
+
+/// A session created on behalf of a lock holder, destroyed when dropped.
+#[derive(Debug)]
+pub(crate) struct OwnedSession {
+    client: Client,
+    pub(crate) id: String,
+    pub(crate) ttl: Duration,
+    destroyed: bool,
+}
+
+impl OwnedSession {
+    /// `behavior` is `release` or `delete`, what happens to the keys the session holds
+    /// when it is invalidated.
+    pub(crate) async fn create(
+        client: &Client,
+        name: &str,
+        ttl: Duration,
+        lock_delay: Option<Duration>,
+        behavior: &str,
+    ) -> Result<OwnedSession> {
+        let entry = SessionEntry {
+            Name: Some(name.to_owned()),
+            TTL: Some(format!("{}s", ttl.as_secs())),
+            LockDelay: lock_delay.map(|d| d.as_nanos() as u64),
+            Behavior: Some(behavior.to_owned()),
+            ..Default::default()
+        };
+        let (created, _) = client.create(&entry, None).await?;
+        let id = created.ID.ok_or_else(|| Error::UnexpectedResponse {
+            path: String::from("/v1/session/create"),
+            message: "session without an ID",
+        })?;
+        Ok(OwnedSession {
+            client: client.clone(),
+            id,
+            ttl,
+            destroyed: false,
+        })
+    }
+
+    pub(crate) async fn destroy(mut self) -> Result<()> {
+        self.destroyed = true;
+        Session::destroy(&self.client, &self.id, None)
+            .await
+            .map(|_| ())
+    }
+}
+
+impl Drop for OwnedSession {
+    fn drop(&mut self) {
+        if self.destroyed {
+            return;
+        }
+        let client = self.client.clone();
+        let id = self.id.clone();
+        spawn(async move {
+            let _ = Session::destroy(&client, &id, None).await;
+        });
+    }
+}
+
+/// Runs cleanup in the background when a runtime is available. Without one the session
+/// TTL eventually frees whatever was held.
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    if let Ok(handle) = Handle::try_current() {
+        handle.spawn(future);
+    }
+}
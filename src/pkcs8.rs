@@ -0,0 +1,134 @@
+//! Conversion of PEM private keys to PKCS#8, the only format `native_tls::Identity` reads.
+//!
+//! `consul tls cert create` writes EC keys as SEC1 (`EC PRIVATE KEY`), and older tooling
+//! writes RSA keys as PKCS#1 (`RSA PRIVATE KEY`). Both are wrapped, unchanged, in a PKCS#8
+//! `PrivateKeyInfo` naming their algorithm.
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OID: u8 = 0x06;
+/// `[0]`, the optional curve parameters of a SEC1 key.
+const EC_PARAMETERS: u8 = 0xa0;
+
+/// 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+/// 1.2.840.10045.2.1
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+/// Returns `pem` as a PKCS#8 PEM key.
+pub(crate) fn to_pkcs8(pem: &str) -> Result<String, &'static str> {
+    let (label, der) = decode_pem(pem)?;
+    let algorithm = match label.as_str() {
+        "PRIVATE KEY" => return Ok(pem.to_owned()),
+        "RSA PRIVATE KEY" => [tlv(OID, RSA_ENCRYPTION), tlv(NULL, &[])].concat(),
+        "EC PRIVATE KEY" => [tlv(OID, EC_PUBLIC_KEY), ec_curve(&der)?].concat(),
+        "ENCRYPTED PRIVATE KEY" => return Err("encrypted keys are not supported"),
+        _ => return Err("no PEM private key found"),
+    };
+    let info = tlv(
+        SEQUENCE,
+        &[
+            tlv(INTEGER, &[0]),
+            tlv(SEQUENCE, &algorithm),
+            tlv(OCTET_STRING, &der),
+        ]
+        .concat(),
+    );
+    Ok(encode_pem("PRIVATE KEY", &info))
+}
+
+/// The named curve OID of a SEC1 `ECPrivateKey`, with its tag and length.
+fn ec_curve(der: &[u8]) -> Result<Vec<u8>, &'static str> {
+    const INVALID: &str = "invalid EC private key";
+
+    let (tag, mut fields, _) = read_tlv(der).ok_or(INVALID)?;
+    if tag != SEQUENCE {
+        return Err(INVALID);
+    }
+    while !fields.is_empty() {
+        let (tag, value, rest) = read_tlv(fields).ok_or(INVALID)?;
+        if tag == EC_PARAMETERS {
+            return match read_tlv(value) {
+                Some((OID, _, _)) => Ok(value.to_vec()),
+                _ => Err("EC private key doesn't name its curve"),
+            };
+        }
+        fields = rest;
+    }
+    Err("EC private key doesn't name its curve")
+}
+
+/// Splits a DER element into its tag, value and the bytes after it.
+fn read_tlv(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let octets = (first & 0x7f) as usize;
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return None;
+        }
+        let len = rest[..octets]
+            .iter()
+            .fold(0, |len, &b| (len << 8) | b as usize);
+        (len, &rest[octets..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let octets: Vec<u8> = len
+            .to_be_bytes()
+            .iter()
+            .copied()
+            .skip_while(|&b| b == 0)
+            .collect();
+        out.push(0x80 | octets.len() as u8);
+        out.extend(octets);
+    }
+    out.extend_from_slice(value);
+    out
+}
+
+/// The label and contents of the first PEM block.
+fn decode_pem(pem: &str) -> Result<(String, Vec<u8>), &'static str> {
+    const NOT_PEM: &str = "no PEM private key found";
+
+    let start = pem.find("-----BEGIN ").ok_or(NOT_PEM)?;
+    let block = &pem[start + "-----BEGIN ".len()..];
+    let label_end = block.find("-----").ok_or(NOT_PEM)?;
+    let label = &block[..label_end];
+    let body = &block[label_end + "-----".len()..];
+    let end = body
+        .find(&format!("-----END {}-----", label))
+        .ok_or(NOT_PEM)?;
+    if body[..end].contains(':') {
+        // Headers such as `Proc-Type: 4,ENCRYPTED`.
+        return Err("encrypted keys are not supported");
+    }
+    let base64: String = body[..end].split_whitespace().collect();
+    let der = base64::decode(&base64).map_err(|_| "invalid PEM encoding")?;
+    Ok((label.to_owned(), der))
+}
+
+fn encode_pem(label: &str, der: &[u8]) -> String {
+    let base64 = base64::encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in base64.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
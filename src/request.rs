@@ -1,89 +1,83 @@
 use std::collections::HashMap;
 use url::Url;
 
-use std::str;
+use futures::stream::{BoxStream, StreamExt};
+use std::error::Error as StdError;
+use std::io;
+use std::path::Path;
 use std::str::FromStr;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use tokio::time::delay_for;
 
 use reqwest::Client as HttpClient;
 use reqwest::RequestBuilder;
+use reqwest::Response;
 use reqwest::header::HeaderValue;
 use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::errors::{Result, ResultExt};
-use crate::{Config, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
+use crate::errors::{Error, Result};
+use crate::transport::{self, SendError, ServerNameTls};
+use crate::{Config, ConsistencyMode, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
 
 fn add_config_options(builder: RequestBuilder, config: &Config) -> RequestBuilder {
-    match &config.token {
+    let builder = match &config.token {
         Some(val) => builder.header("X-Consul-Token", val),
         None => builder,
+    };
+    match &config.http_auth {
+        Some(auth) => builder.basic_auth(&auth.username, auth.password.as_ref()),
+        None => builder,
+    }
+}
+
+/// Adds the `dc`, `ns` and `partition` parameters. Values set on the request options take
+/// precedence over the ones from the configuration.
+fn add_scope_params(
+    params: &mut HashMap<String, String>,
+    config: &Config,
+    datacenter: Option<&String>,
+    namespace: Option<&String>,
+    partition: Option<&String>,
+) {
+    let scopes = [
+        ("dc", datacenter.or(config.datacenter.as_ref())),
+        ("ns", namespace.or(config.namespace.as_ref())),
+        ("partition", partition.or(config.partition.as_ref())),
+    ];
+    for (name, value) in scopes.iter() {
+        if let Some(value) = value {
+            params.insert(String::from(*name), value.to_string());
+        }
     }
 }
 
 pub async fn get_vec<R: DeserializeOwned>(
     path: &str,
     config: &Config,
-    mut params: HashMap<String, String>,
+    params: HashMap<String, String>,
     options: Option<&QueryOptions>,
 ) -> Result<(Vec<R>, QueryMeta)> {
-    let datacenter: Option<&String> = options
-        .and_then(|o| o.datacenter.as_ref())
-        .or_else(|| config.datacenter.as_ref());
-
-    if let Some(dc) = datacenter {
-        params.insert(String::from("dc"), dc.to_owned());
-    }
-    if let Some(options) = options {
-        if let Some(index) = options.wait_index {
-            params.insert(String::from("index"), index.to_string());
-        }
-        if let Some(wait_time) = options.wait_time {
-            params.insert(String::from("wait"), format!("{}s", wait_time.as_secs()));
-        }
-    }
+    let (response, start) = send_get(path, config, params, options).await?;
 
-    let url_str = format!("{}{}", config.address, path);
-    let url =
-        Url::parse_with_params(&url_str, params.iter()).chain_err(|| "Failed to parse URL")?;
-    let start = Instant::now();
-    let request_builder = add_config_options(config.http_client.get(url), &config);
-    let response = request_builder
-        .send()
-        .await
-        .chain_err(|| "HTTP request to consul failed")?;
-
-    let x: Option<Result<u64>> = response
-        .headers()
-        .get("X-Consul-Index").map(|value: &HeaderValue| value.as_bytes())
-        .map(|bytes| {
-            str::from_utf8(bytes)
-                .chain_err(|| "Failed to parse valid UTF-8 for last index")
-                .and_then(|s| {
-                    u64::from_str(s)
-                        .chain_err(|| "Failed to parse valid integer for last index")
-                })
-        });
-
-    let j = if response.status() != StatusCode::NOT_FOUND {
-        response.json().await.chain_err(|| "Failed to parse JSON response")?
+    // A missing collection is empty rather than an error.
+    let (j, meta) = if response.status() != StatusCode::NOT_FOUND {
+        let response = check_status(path, response).await?;
+        let meta = parse_query_meta(path, &response)?;
+        let body = response.bytes().await.map_err(|e| Error::http(path, e))?;
+        (decode_json(path, &body)?, meta)
     } else {
-        Vec::new()
+        (Vec::new(), parse_query_meta(path, &response)?)
     };
 
-    match x {
-        Some(response) => Ok((j, Some(response?))),
-        None => Ok((j, None)),
-    }.map(|x: (Vec<R>, Option<u64>)| {
-        (
-            x.0,
-            QueryMeta {
-                last_index: x.1,
-                request_time: Instant::now() - start,
-            },
-        )
-    })
+    Ok((
+        j,
+        QueryMeta {
+            request_time: Instant::now() - start,
+            ..meta
+        },
+    ))
 }
 
 pub async fn get<R: DeserializeOwned>(
@@ -92,12 +86,9 @@ pub async fn get<R: DeserializeOwned>(
     params: HashMap<String, String>,
     options: Option<&QueryOptions>,
 ) -> Result<(R, QueryMeta)> {
-    let raw = get_raw(path, config, params, options)
-        .await
-        .chain_err(|| "Failed to get raw response")?;
+    let raw = get_raw(path, config, params, options).await?;
 
-    let json: R = serde_json::from_str(&raw.0)
-        .chain_err(|| "Failed to parse JSON")?;
+    let json: R = decode_json(path, raw.0.as_bytes())?;
 
     Ok((json, raw.1))
 }
@@ -105,64 +96,413 @@ pub async fn get<R: DeserializeOwned>(
 pub async fn get_raw(
     path: &str,
     config: &Config,
-    mut params: HashMap<String, String>,
+    params: HashMap<String, String>,
     options: Option<&QueryOptions>,
 ) -> Result<(String, QueryMeta)> {
-    let datacenter: Option<&String> = options
-        .and_then(|o| o.datacenter.as_ref())
-        .or_else(|| config.datacenter.as_ref());
+    let (response, start) = send_get(path, config, params, options).await?;
+    let response = check_status(path, response).await?;
+    let meta = parse_query_meta(path, &response)?;
+
+    let j = response.text().await.map_err(|e| Error::http(path, e))?;
+    Ok((
+        j,
+        QueryMeta {
+            request_time: Instant::now() - start,
+            ..meta
+        },
+    ))
+}
+
+/// Fetches and decodes a response body, or `None` if the path doesn't exist or Consul
+/// answers with `null`.
+pub async fn get_optional<R: DeserializeOwned>(
+    path: &str,
+    config: &Config,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(Option<R>, QueryMeta)> {
+    let (bytes, meta) = get_bytes(path, config, params, options).await?;
+    let json = match bytes {
+        Some(body) => decode_json(path, &body)?,
+        None => None,
+    };
+    Ok((json, meta))
+}
+
+/// Fetches an undecoded response body, or `None` if the path doesn't exist.
+pub async fn get_bytes(
+    path: &str,
+    config: &Config,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(Option<Vec<u8>>, QueryMeta)> {
+    let (response, start) = send_get(path, config, params, options).await?;
+
+    let (bytes, meta) = if response.status() != StatusCode::NOT_FOUND {
+        let response = check_status(path, response).await?;
+        let meta = parse_query_meta(path, &response)?;
+        let body = response.bytes().await.map_err(|e| Error::http(path, e))?;
+        (Some(body.to_vec()), meta)
+    } else {
+        (None, parse_query_meta(path, &response)?)
+    };
+    Ok((
+        bytes,
+        QueryMeta {
+            request_time: Instant::now() - start,
+            ..meta
+        },
+    ))
+}
 
-    if let Some(dc) = datacenter {
-        params.insert(String::from("dc"), dc.to_owned());
+/// Streams the response body in chunks as they arrive, for endpoints that keep the
+/// response open such as `/v1/agent/monitor`.
+pub async fn get_stream(
+    path: &str,
+    config: &Config,
+    params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<BoxStream<'static, Result<Vec<u8>>>> {
+    let (response, _) = send_get(path, config, params, options).await?;
+    let response = check_status(path, response).await?;
+    let path = path.to_owned();
+    Ok(response
+        .bytes_stream()
+        .map(move |chunk| {
+            chunk
+                .map(|bytes| bytes.to_vec())
+                .map_err(|e| Error::http(&path, e))
+        })
+        .boxed())
+}
+
+async fn send_get(
+    path: &str,
+    config: &Config,
+    mut params: HashMap<String, String>,
+    options: Option<&QueryOptions>,
+) -> Result<(Response, Instant)> {
+    add_scope_params(
+        &mut params,
+        config,
+        options.and_then(|o| o.datacenter.as_ref()),
+        options.and_then(|o| o.namespace.as_ref()),
+        options.and_then(|o| o.partition.as_ref()),
+    );
+    if let Some(index) = options.and_then(|o| o.wait_index) {
+        params.insert(String::from("index"), index.to_string());
+    }
+    // Consul only waits when an index is given, so the default can always be sent.
+    let wait_time = options.and_then(|o| o.wait_time).or(config.wait_time);
+    if let Some(wait_time) = wait_time {
+        params.insert(String::from("wait"), format!("{}ms", wait_time.as_millis()));
     }
     if let Some(options) = options {
-        if let Some(index) = options.wait_index {
-            params.insert(String::from("index"), index.to_string());
+        add_query_params(&mut params, options)?;
+    }
+
+    let mut url = build_url(path, config, &params)?;
+    if let Some(options) = options {
+        // Unlike the other parameters, `node-meta` is repeated once per pair.
+        let mut pairs = url.query_pairs_mut();
+        for (key, value) in options.node_meta.iter() {
+            pairs.append_pair("node-meta", &format!("{}:{}", key, value));
+        }
+    }
+    let max_age = options.filter(|o| o.use_cache).and_then(|o| o.max_age);
+    let start = Instant::now();
+    let response = send(path, config, true, || {
+        let builder = config.http_client.get(url.clone());
+        let builder = match max_age {
+            Some(max_age) => {
+                builder.header("Cache-Control", format!("max-age={}", max_age.as_secs()))
+            }
+            None => builder,
+        };
+        add_config_options(builder, config)
+    })
+    .await?;
+    Ok((response, start))
+}
+
+/// Adds the filtering, sorting, consistency and caching parameters of a read.
+fn add_query_params(params: &mut HashMap<String, String>, options: &QueryOptions) -> Result<()> {
+    if let Some(ref filter) = options.filter {
+        params.insert(String::from("filter"), filter.clone());
+    }
+    if let Some(ref near) = options.near {
+        params.insert(String::from("near"), near.clone());
+    }
+    if options.require_consistent && options.allow_stale {
+        return Err(Error::InvalidArgument(
+            "require_consistent and allow_stale are mutually exclusive",
+        ));
+    }
+    match options.consistency() {
+        ConsistencyMode::Consistent => {
+            params.insert(String::from("consistent"), String::new());
         }
-        if let Some(wait_time) = options.wait_time {
-            params.insert(String::from("wait"), format!("{}s", wait_time.as_secs()));
+        ConsistencyMode::Stale => {
+            params.insert(String::from("stale"), String::new());
         }
+        ConsistencyMode::Default => {}
+    }
+    if options.use_cache {
+        params.insert(String::from("cached"), String::new());
     }
+    Ok(())
+}
 
-    let url_str = format!("{}{}", config.address, path);
-    let url =
-        Url::parse_with_params(&url_str, params.iter()).chain_err(|| "Failed to parse URL")?;
-    let start = Instant::now();
-    let request_builder = add_config_options(config.http_client.get(url), &config);
-    let response = request_builder
-        .send()
-        .await
-        .chain_err(|| "HTTP request to consul failed")?;
+/// The socket path of a `unix://` address.
+fn unix_socket(config: &Config) -> Option<&Path> {
+    config.address.strip_prefix("unix://").map(Path::new)
+}
+
+fn build_url(path: &str, config: &Config, params: &HashMap<String, String>) -> Result<Url> {
+    // Requests over a unix socket still need an HTTP URL to be built.
+    let base = match unix_socket(config) {
+        Some(_) => "http://localhost",
+        None => &config.address,
+    };
+    let url_str = format!("{}{}", base, path);
+    Url::parse_with_params(&url_str, params.iter()).map_err(|e| Error::InvalidUrl {
+        path: path.to_owned(),
+        source: e,
+    })
+}
 
-    let x: Option<Result<u64>> = response
+/// Reads the metadata Consul sends in the headers of a read. `request_time` is left to the
+/// caller, which knows when the body has been read.
+///
+/// The index and last contact time are checked, since blocking queries and staleness checks
+/// rely on them. The other headers are informational and ignored if they can't be parsed,
+/// e.g. after being rewritten by a proxy.
+fn parse_query_meta(path: &str, response: &Response) -> Result<QueryMeta> {
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    let consistency = match header("X-Consul-Effective-Consistency") {
+        Some("leader") => Some(ConsistencyMode::Default),
+        Some("consistent") => Some(ConsistencyMode::Consistent),
+        Some("stale") => Some(ConsistencyMode::Stale),
+        _ => None,
+    };
+    let last_contact = parse_header(path, response, "X-Consul-LastContact", |s| {
+        u64::from_str(s).ok().map(Duration::from_millis)
+    })?;
+
+    Ok(QueryMeta {
+        last_index: parse_header(path, response, "X-Consul-Index", |s| u64::from_str(s).ok())?,
+        request_time: Duration::default(),
+        known_leader: header("X-Consul-KnownLeader") == Some("true"),
+        last_contact: last_contact.unwrap_or_default(),
+        consistency,
+        cache_hit: header("X-Cache") == Some("HIT"),
+        cache_age: header("Age")
+            .and_then(|s| u64::from_str(s).ok())
+            .map(Duration::from_secs),
+        default_acl_policy: header("X-Consul-Default-ACL-Policy").map(String::from),
+        results_filtered_by_acls: header("X-Consul-Results-Filtered-By-ACLs") == Some("true"),
+    })
+}
+
+/// Parses the `name` header if present, failing if `parse` rejects its value.
+fn parse_header<T, F>(
+    path: &str,
+    response: &Response,
+    name: &'static str,
+    parse: F,
+) -> Result<Option<T>>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    response
         .headers()
-        .get("X-Consul-Index")
-        .map(|bytes: &HeaderValue| -> Result<u64> {
-            bytes
+        .get(name)
+        .map(|value: &HeaderValue| -> Result<T> {
+            value
                 .to_str()
-                .chain_err(|| "Failed to parse valid UTF-8 for last index")
-                .and_then(|s: &str| -> Result<u64> {
-                    u64::from_str(s)
-                        .chain_err(|| "Failed to parse valid integer for last index")
+                .ok()
+                .and_then(parse)
+                .ok_or_else(|| Error::InvalidHeader {
+                    path: path.to_owned(),
+                    header: name,
+                    value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
                 })
-        });
-
-    let j = response.text().await.chain_err(|| "Failed to get raw response")?;
-    match x {
-        Some(r) => Ok((j, Some(r?))),
-        None => Ok((j, None)),
-    }
-    .map(|x: (String, Option<u64>)| {
-        (
-            x.0,
-            QueryMeta {
-                last_index: x.1,
-                request_time: Instant::now() - start,
+        })
+        .transpose()
+}
+
+/// Sends the request made by `build`, retrying according to the configured `RetryPolicy`.
+/// Requests that aren't `idempotent` are only retried if they never reached Consul.
+async fn send<F>(path: &str, config: &Config, idempotent: bool, build: F) -> Result<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let policy = &config.retry_policy;
+    let mut attempt = 1;
+    loop {
+        let result = execute(path, config, build()).await;
+        let retryable = match result {
+            Ok(ref response) => {
+                idempotent && policy.retryable_statuses.contains(&response.status())
+            }
+            Err(ref e) => is_connect(e) || (idempotent && is_transient(e)),
+        };
+        if !retryable || attempt >= policy.max_attempts {
+            return result;
+        }
+        delay_for(policy.backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Sends a single request, over the agent's unix socket if `address` names one, or with the
+/// certificate verified against the TLS server name if the host is another one.
+async fn execute(path: &str, config: &Config, builder: RequestBuilder) -> Result<Response> {
+    if let Some(socket) = unix_socket(config) {
+        let request = builder.build().map_err(|e| Error::http(path, e))?;
+        return send_unix(path, config, socket, request).await;
+    }
+    let tls = match config.server_name_tls {
+        Some(ref tls) => tls,
+        None => return builder.send().await.map_err(|e| Error::http(path, e)),
+    };
+    let request = builder.build().map_err(|e| Error::http(path, e))?;
+    let url = request.url();
+    if url.scheme() != "https" || url.host_str() == Some(tls.server_name.as_str()) {
+        return config
+            .http_client
+            .execute(request)
+            .await
+            .map_err(|e| Error::http(path, e));
+    }
+    send_tls(path, config, tls, request).await
+}
+
+#[cfg(unix)]
+async fn send_unix(
+    path: &str,
+    config: &Config,
+    socket: &Path,
+    request: reqwest::Request,
+) -> Result<Response> {
+    transport::send_unix(socket, request, config.timeout)
+        .await
+        .map_err(|e| match e {
+            SendError::Timeout(e) => timeout_error(path, e),
+            e => Error::Socket {
+                path: path.to_owned(),
+                socket: socket.to_owned(),
+                source: send_error_source(e),
+            },
+        })
+}
+
+#[cfg(not(unix))]
+async fn send_unix(_: &str, _: &Config, _: &Path, _: reqwest::Request) -> Result<Response> {
+    Err(Error::InvalidArgument(
+        "unix socket addresses are only supported on unix platforms",
+    ))
+}
+
+async fn send_tls(
+    path: &str,
+    config: &Config,
+    tls: &ServerNameTls,
+    request: reqwest::Request,
+) -> Result<Response> {
+    transport::send_tls(tls, request, config.timeout)
+        .await
+        .map_err(|e| match e {
+            SendError::Timeout(e) => timeout_error(path, e),
+            e => Error::Https {
+                path: path.to_owned(),
+                source: send_error_source(e),
             },
-        )
+        })
+}
+
+fn timeout_error(path: &str, source: tokio::time::Elapsed) -> Error {
+    Error::Timeout {
+        path: path.to_owned(),
+        source: Box::new(source),
+    }
+}
+
+fn send_error_source(e: SendError) -> Box<dyn StdError + Send + Sync> {
+    match e {
+        SendError::Build(e) => Box::new(e),
+        SendError::Connect(e) => Box::new(e),
+        SendError::Tls(e) => Box::new(e),
+        SendError::Request(e) => Box::new(e),
+        SendError::Timeout(e) => Box::new(e),
+    }
+}
+
+/// Whether the request failed before reaching Consul.
+fn is_connect(e: &Error) -> bool {
+    match e {
+        Error::Http { source, .. } => source.is_connect(),
+        Error::Socket { source, .. } | Error::Https { source, .. } => source.is::<io::Error>(),
+        _ => false,
+    }
+}
+
+fn is_transient(e: &Error) -> bool {
+    let mut source = match e {
+        Error::Timeout { .. } => return true,
+        Error::Http { source, .. } => source.source(),
+        Error::Socket { source, .. } | Error::Https { source, .. } => source.source(),
+        _ => return false,
+    };
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Turns a non-success response into `Error::Status`, keeping the body Consul sent.
+async fn check_status(path: &str, response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(Error::Status {
+        path: path.to_owned(),
+        status,
+        body,
+    })
+}
+
+fn decode_json<R: DeserializeOwned>(path: &str, body: &[u8]) -> Result<R> {
+    serde_json::from_slice(body).map_err(|e| Error::Decode {
+        path: path.to_owned(),
+        source: e,
     })
 }
 
+/// Decodes `null` as the default value, for collections Consul leaves as `null` when empty.
+pub(crate) fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
 pub async fn delete<R: DeserializeOwned>(
     path: &str,
     config: &Config,
@@ -170,7 +510,16 @@ pub async fn delete<R: DeserializeOwned>(
     options: Option<&WriteOptions>,
 ) -> Result<(R, WriteMeta)> {
     let req = |http_client: &HttpClient, url: Url| -> RequestBuilder { http_client.delete(url) };
-    write_with_body(path, None as Option<Body<()>>, config, params, options, req).await
+    write_with_body(
+        path,
+        None as Option<Body<()>>,
+        config,
+        params,
+        options,
+        true,
+        req,
+    )
+    .await
 }
 
 pub async fn put<T: Serialize, R: DeserializeOwned>(
@@ -181,13 +530,15 @@ pub async fn put<T: Serialize, R: DeserializeOwned>(
     options: Option<&WriteOptions>,
 ) -> Result<(R, WriteMeta)> {
     let req = |http_client: &HttpClient, url: Url| -> RequestBuilder { http_client.put(url) };
-    write_with_body(path, body, config, params, options, req).await
+    // A check-and-set write can't be applied twice, so it is safe to retry.
+    let idempotent = params.contains_key("cas");
+    write_with_body(path, body, config, params, options, idempotent, req).await
 }
 
 #[derive(Debug)]
 pub enum Body<T: Serialize> {
     AsJson(T),
-    AsText(String),
+    AsBytes(Vec<u8>),
 }
 
 async fn write_with_body<T: Serialize, R: DeserializeOwned, F>(
@@ -196,44 +547,41 @@ async fn write_with_body<T: Serialize, R: DeserializeOwned, F>(
     config: &Config,
     mut params: HashMap<String, String>,
     options: Option<&WriteOptions>,
+    idempotent: bool,
     req: F,
 ) -> Result<(R, WriteMeta)>
 where
     F: Fn(&HttpClient, Url) -> RequestBuilder,
 {
     let start = Instant::now();
-    let datacenter: Option<&String> = options
-        .and_then(|o| o.datacenter.as_ref())
-        .or_else(|| config.datacenter.as_ref());
-
-    if let Some(dc) = datacenter {
-        params.insert(String::from("dc"), dc.to_owned());
-    }
-
-    let url_str = format!("{}{}", config.address, path);
-    let url = Url::parse_with_params(&url_str, params.iter()).chain_err(|| "Failed to parse URL")?;
-    
-    let builder = req(&config.http_client, url);
-    
-    let builder = if let Some(b) = body {
-        match b {
-            Body::AsJson(json) => builder.json(&json),
-            Body::AsText(string) => builder.body(string)
-        }
-    } else {
-        builder
-    };
-    
-    let builder = add_config_options(builder, &config);
-    
-    let response = builder
-        .send()
-        .await
-        .chain_err(|| "HTTP request to consul failed")?;
+    add_scope_params(
+        &mut params,
+        config,
+        options.and_then(|o| o.datacenter.as_ref()),
+        options.and_then(|o| o.namespace.as_ref()),
+        options.and_then(|o| o.partition.as_ref()),
+    );
 
-    let json = response.json::<R>()
-        .await
-        .chain_err(|| "Failed to parse JSON")?;
+    let url = build_url(path, config, &params)?;
+
+    let response = send(path, config, idempotent, || {
+        let builder = req(&config.http_client, url.clone());
+
+        let builder = match body {
+            Some(Body::AsJson(ref json)) => builder.json(json),
+            Some(Body::AsBytes(ref bytes)) => builder.body(bytes.clone()),
+            None => builder,
+        };
+
+        add_config_options(builder, config)
+    })
+    .await?;
+
+    let response = check_status(path, response).await?;
+    let body = response.bytes().await.map_err(|e| Error::http(path, e))?;
+    // Some endpoints answer with an empty body, which stands for `null`.
+    let body: &[u8] = if body.is_empty() { b"null" } else { &body };
+    let json: R = decode_json(path, body)?;
 
     Ok((
         json,
@@ -0,0 +1,371 @@
+use futures::{Future, StreamExt};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::errors::{Error, Result};
+use crate::kv::{KVPair, KV};
+use crate::lock::{spawn, Monitor, OwnedSession};
+use crate::{Client, QueryOptions};
+
+/// `Flags` value marking keys that belong to a semaphore. Shared with the Go client.
+pub const SEMAPHORE_FLAG_VALUE: u64 = 0xe0f6_9a2b_aa41_4de0;
+
+/// Name of the key under the prefix holding the limit and the current holders.
+pub const DEFAULT_SEMAPHORE_KEY: &str = ".lock";
+
+pub const DEFAULT_SEMAPHORE_SESSION_NAME: &str = "Consul API Semaphore";
+pub const DEFAULT_SEMAPHORE_SESSION_TTL: Duration = Duration::from_secs(15);
+pub const DEFAULT_SEMAPHORE_WAIT_TIME: Duration = Duration::from_secs(15);
+
+/// Settings for a `Semaphore`, mirroring `api.SemaphoreOptions` from the Go client.
+#[derive(Clone, Debug)]
+pub struct SemaphoreOptions {
+    pub prefix: String,
+    pub limit: usize,
+    /// Value stored in this contender's key.
+    pub value: Option<Vec<u8>>,
+    /// Existing session to contend with. When unset a session is created, renewed in the
+    /// background and destroyed on release.
+    pub session: Option<String>,
+    pub session_name: String,
+    pub session_ttl: Duration,
+    /// How long each blocking query waits while the semaphore is full.
+    pub semaphore_wait_time: Duration,
+    /// Number of failed queries tolerated while monitoring a held slot.
+    pub monitor_retries: u32,
+}
+
+impl SemaphoreOptions {
+    pub fn new(prefix: &str, limit: usize) -> SemaphoreOptions {
+        SemaphoreOptions {
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            limit,
+            value: None,
+            session: None,
+            session_name: String::from(DEFAULT_SEMAPHORE_SESSION_NAME),
+            session_ttl: DEFAULT_SEMAPHORE_SESSION_TTL,
+            semaphore_wait_time: DEFAULT_SEMAPHORE_WAIT_TIME,
+            monitor_retries: 0,
+        }
+    }
+
+    fn lock_key(&self) -> String {
+        format!("{}/{}", self.prefix, DEFAULT_SEMAPHORE_KEY)
+    }
+
+    fn contender_key(&self, session: &str) -> String {
+        format!("{}/{}", self.prefix, session)
+    }
+}
+
+/// Contents of the `.lock` key, in the same format the Go client writes.
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SemaphoreLock {
+    pub Limit: usize,
+    pub Holders: HashMap<String, bool>,
+}
+
+/// A counting semaphore allowing up to `limit` concurrent holders, compatible with
+/// `consul lock -n`.
+///
+/// See https://www.consul.io/docs/guides/semaphore.html for the underlying protocol.
+#[derive(Clone, Debug)]
+pub struct Semaphore {
+    client: Client,
+    options: SemaphoreOptions,
+}
+
+impl Semaphore {
+    pub fn new(client: &Client, options: SemaphoreOptions) -> Semaphore {
+        Semaphore {
+            client: client.clone(),
+            options,
+        }
+    }
+
+    /// Waits until a slot is held.
+    pub async fn acquire(&self) -> Result<SemaphoreGuard> {
+        self.semaphore(false)
+            .await?
+            .ok_or_else(|| Error::NotAcquired {
+                key: self.options.prefix.clone(),
+            })
+    }
+
+    /// Makes a single attempt, returning `None` if every slot is taken.
+    pub async fn try_acquire(&self) -> Result<Option<SemaphoreGuard>> {
+        self.semaphore(true).await
+    }
+
+    async fn semaphore(&self, try_once: bool) -> Result<Option<SemaphoreGuard>> {
+        if self.options.limit == 0 {
+            return Err(Error::InvalidArgument("Semaphore limit must be positive"));
+        }
+        let owned = match self.options.session {
+            Some(_) => None,
+            None => Some(
+                OwnedSession::create(
+                    &self.client,
+                    &self.options.session_name,
+                    self.options.session_ttl,
+                    None,
+                    // Contender keys are named after the session, so they go with it.
+                    "delete",
+                )
+                .await?,
+            ),
+        };
+        let session = match owned {
+            Some(ref s) => s.id.clone(),
+            None => self.options.session.clone().unwrap_or_default(),
+        };
+
+        let contender = KVPair {
+            Key: self.options.contender_key(&session),
+            Value: self.options.value.clone(),
+            Flags: Some(SEMAPHORE_FLAG_VALUE),
+            Session: Some(session.clone()),
+            ..Default::default()
+        };
+        let (made, _) = self.client.acquire(&contender, None).await?;
+        if !made {
+            return Err(Error::NotAcquired { key: contender.Key });
+        }
+
+        let held = self.claim_slot(&session, try_once).await;
+        if let Ok(true) = held {
+            return Ok(Some(SemaphoreGuard::new(
+                &self.client,
+                &self.options,
+                session,
+                owned,
+            )));
+        }
+        let _ = self.client.delete(&contender.Key, None).await;
+        held.map(|_| None)
+    }
+
+    /// Adds `session` to the holders of the `.lock` key once a slot is free.
+    async fn claim_slot(&self, session: &str, try_once: bool) -> Result<bool> {
+        let mut query = QueryOptions {
+            wait_time: Some(self.options.semaphore_wait_time),
+            ..Default::default()
+        };
+        loop {
+            let (pairs, meta) = self
+                .client
+                .list(&format!("{}/", self.options.prefix), Some(&query))
+                .await?;
+            let lock_key = self.options.lock_key();
+            let lock_pair = pairs.iter().find(|p| p.Key == lock_key);
+            let mut lock = decode_lock(lock_pair, self.options.limit)?;
+            if lock.Limit != self.options.limit {
+                return Err(Error::SemaphoreLimitConflict {
+                    lock: lock.Limit,
+                    local: self.options.limit,
+                });
+            }
+
+            prune_dead_holders(&mut lock, &pairs);
+            if lock.Holders.len() >= lock.Limit {
+                if try_once {
+                    return Ok(false);
+                }
+                query.wait_index = meta.last_index;
+                continue;
+            }
+
+            lock.Holders.insert(session.to_owned(), true);
+            let modify_index = lock_pair.and_then(|p| p.ModifyIndex).unwrap_or(0);
+            if write_lock(&self.client, &self.options, &lock, modify_index).await? {
+                return Ok(true);
+            }
+            query.wait_index = None;
+        }
+    }
+}
+
+/// Proof of holding a `Semaphore` slot. The slot is released when the guard is dropped.
+#[derive(Debug)]
+pub struct SemaphoreGuard {
+    client: Client,
+    options: SemaphoreOptions,
+    session_id: String,
+    session: Option<OwnedSession>,
+    monitor: Monitor,
+    released: bool,
+}
+
+impl SemaphoreGuard {
+    fn new(
+        client: &Client,
+        options: &SemaphoreOptions,
+        session_id: String,
+        session: Option<OwnedSession>,
+    ) -> SemaphoreGuard {
+        let watch = monitor_semaphore(client.clone(), options.clone(), session_id.clone());
+        SemaphoreGuard {
+            client: client.clone(),
+            options: options.clone(),
+            monitor: Monitor::spawn(client, session.as_ref(), watch),
+            session_id,
+            session,
+            released: false,
+        }
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Resolves once this holder is no longer in the semaphore, e.g. because the session
+    /// was invalidated or another contender pruned it.
+    pub fn lost(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.monitor.lost()
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.monitor.is_lost()
+    }
+
+    /// Gives up the slot, removes the contender key and destroys the session if the
+    /// semaphore created it.
+    pub async fn release(mut self) -> Result<()> {
+        self.released = true;
+        self.monitor.stop();
+        release(
+            &self.client,
+            &self.options,
+            &self.session_id,
+            self.session.take(),
+        )
+        .await
+    }
+}
+
+impl Drop for SemaphoreGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.monitor.stop();
+        let client = self.client.clone();
+        let options = self.options.clone();
+        let session_id = self.session_id.clone();
+        let session = self.session.take();
+        spawn(async move {
+            let _ = release(&client, &options, &session_id, session).await;
+        });
+    }
+}
+
+async fn release(
+    client: &Client,
+    options: &SemaphoreOptions,
+    session_id: &str,
+    session: Option<OwnedSession>,
+) -> Result<()> {
+    loop {
+        let (pair, _) = client.get(&options.lock_key(), None).await?;
+        let lock_pair = pair.as_ref();
+        let mut lock = decode_lock(lock_pair, options.limit)?;
+        if lock.Holders.remove(session_id).is_none() {
+            break;
+        }
+        let modify_index = lock_pair.and_then(|p| p.ModifyIndex).unwrap_or(0);
+        if write_lock(client, options, &lock, modify_index).await? {
+            break;
+        }
+    }
+
+    let deleted = client
+        .delete(&options.contender_key(session_id), None)
+        .await
+        .map(|_| ());
+    match session {
+        Some(s) => deleted.and(s.destroy().await),
+        None => deleted,
+    }
+}
+
+async fn monitor_semaphore(client: Client, options: SemaphoreOptions, session: String) {
+    let query = QueryOptions {
+        wait_time: Some(options.semaphore_wait_time),
+        ..Default::default()
+    };
+    let lock_key = options.lock_key();
+    let mut watch = client.watch_prefix(&format!("{}/", options.prefix), Some(&query));
+    let mut failures = 0;
+    while let Some(result) = watch.next().await {
+        let held = result.and_then(|(pairs, _)| {
+            decode_lock(pairs.iter().find(|p| p.Key == lock_key), options.limit)
+                .map(|lock| lock.Holders.contains_key(&session))
+        });
+        match held {
+            Ok(true) => failures = 0,
+            Ok(false) => return,
+            Err(_) => {
+                failures += 1;
+                if failures > options.monitor_retries {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Decodes the `.lock` key, treating a missing key as an empty semaphore of `limit`.
+fn decode_lock(pair: Option<&KVPair>, limit: usize) -> Result<SemaphoreLock> {
+    let pair = match pair {
+        Some(p) => p,
+        None => {
+            return Ok(SemaphoreLock {
+                Limit: limit,
+                Holders: HashMap::new(),
+            })
+        }
+    };
+    if pair.Flags != Some(SEMAPHORE_FLAG_VALUE) {
+        return Err(Error::Conflict {
+            key: pair.Key.clone(),
+            kind: "semaphore",
+        });
+    }
+    match pair.Value {
+        Some(ref value) if !value.is_empty() => {
+            serde_json::from_slice(value).map_err(|e| Error::InvalidValue {
+                key: pair.Key.clone(),
+                source: Box::new(e),
+            })
+        }
+        _ => Ok(SemaphoreLock {
+            Limit: limit,
+            Holders: HashMap::new(),
+        }),
+    }
+}
+
+/// Drops holders whose contender key no longer carries a live session.
+fn prune_dead_holders(lock: &mut SemaphoreLock, pairs: &[KVPair]) {
+    let alive: Vec<&str> = pairs.iter().filter_map(|p| p.Session.as_deref()).collect();
+    lock.Holders.retain(|holder, _| alive.contains(&holder.as_str()));
+}
+
+/// Check-and-set write of the `.lock` key. An index of 0 only creates the key.
+async fn write_lock(
+    client: &Client,
+    options: &SemaphoreOptions,
+    lock: &SemaphoreLock,
+    modify_index: u64,
+) -> Result<bool> {
+    let body = serde_json::to_vec(lock).map_err(Error::Encode)?;
+    let pair = KVPair {
+        Key: options.lock_key(),
+        Value: Some(body),
+        Flags: Some(SEMAPHORE_FLAG_VALUE),
+        ModifyIndex: Some(modify_index),
+        ..Default::default()
+    };
+    client.cas(&pair, None).await.map(|x| x.0)
+}
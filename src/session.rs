@@ -1,18 +1,21 @@
 use async_trait::async_trait;
+use reqwest::StatusCode;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::time::delay_for;
 
-use crate::errors::Result;
+use crate::errors::{Error, Result};
 use crate::request::{get, put, Body};
 use crate::{Client, QueryMeta, QueryOptions, WriteMeta, WriteOptions};
 
-#[serde(default)]
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct SessionID {
     pub ID: String,
 }
 
-#[serde(default)]
 #[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct SessionEntry {
     pub CreateIndex: Option<u64>,
     pub ID: Option<String>,
@@ -117,3 +120,28 @@ impl Session for Client {
         .await
     }
 }
+
+/// Renews the session every half TTL until it expires or can't be renewed for a whole TTL.
+/// Only returns on failure, so callers run it alongside whatever depends on the session.
+pub(crate) async fn renew_periodic(client: Client, id: String, ttl: Duration) -> Result<()> {
+    let mut last_renewed = Instant::now();
+    let mut wait = ttl / 2;
+    loop {
+        delay_for(wait).await;
+        match client.renew(&id, None).await {
+            Ok(_) => {
+                last_renewed = Instant::now();
+                wait = ttl / 2;
+            }
+            Err(Error::Status { status, .. }) if status == StatusCode::NOT_FOUND => {
+                return Err(Error::SessionExpired { id });
+            }
+            Err(e) => {
+                if last_renewed.elapsed() > ttl {
+                    return Err(e);
+                }
+                wait = Duration::from_secs(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! HTTP over connections reqwest can't open itself: the agent's unix domain socket for
+//! `unix://` addresses, and HTTPS verified against a `TlsConfig::server_name` other than
+//! the address host.
+//!
+//! reqwest can't use a custom connector, so requests are built with reqwest as usual and
+//! sent over a fresh connection with hyper.
+
+use std::future::Future;
+use std::io;
+#[cfg(unix)]
+use std::path::Path;
+use std::time::Duration;
+
+use hyper::header::{HeaderValue, HOST};
+use hyper::Body as HyperBody;
+use reqwest::{Body, Request, Response};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::time::{timeout, Elapsed};
+use tokio_tls::TlsConnector;
+
+pub(crate) enum SendError {
+    /// The reqwest request couldn't be turned into a hyper one.
+    Build(hyper::http::Error),
+    /// Nothing is listening at the address.
+    Connect(io::Error),
+    /// The TLS handshake failed, e.g. because the certificate doesn't match the server name.
+    Tls(native_tls::Error),
+    Request(hyper::Error),
+    /// No response arrived within the configured timeout.
+    Timeout(Elapsed),
+}
+
+/// Connects to the address host, but verifies its certificate against `server_name`.
+#[derive(Clone, Debug)]
+pub(crate) struct ServerNameTls {
+    pub(crate) connector: TlsConnector,
+    pub(crate) server_name: String,
+}
+
+/// Sends `request` over `socket`, giving up if the response head doesn't arrive within
+/// `limit`, like the timeout of the HTTP client.
+#[cfg(unix)]
+pub(crate) async fn send_unix(
+    socket: &Path,
+    request: Request,
+    limit: Option<Duration>,
+) -> Result<Response, SendError> {
+    let send = async {
+        let stream = UnixStream::connect(socket)
+            .await
+            .map_err(SendError::Connect)?;
+        send_over(stream, request, HeaderValue::from_static("localhost")).await
+    };
+    with_timeout(limit, send).await
+}
+
+/// Sends `request` over HTTPS to the host and port of its URL, with the certificate
+/// verified against `tls.server_name`.
+pub(crate) async fn send_tls(
+    tls: &ServerNameTls,
+    request: Request,
+    limit: Option<Duration>,
+) -> Result<Response, SendError> {
+    let send = async {
+        let url = request.url();
+        let host = url.host_str().unwrap_or_default().to_owned();
+        let port = url.port_or_known_default().unwrap_or(443);
+        let authority = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.clone(),
+        };
+        let authority = HeaderValue::from_str(&authority)
+            .map_err(|e| SendError::Build(hyper::http::Error::from(e)))?;
+        let stream = TcpStream::connect((host.as_str(), port))
+            .await
+            .map_err(SendError::Connect)?;
+        let stream = tls
+            .connector
+            .connect(&tls.server_name, stream)
+            .await
+            .map_err(SendError::Tls)?;
+        send_over(stream, request, authority).await
+    };
+    with_timeout(limit, send).await
+}
+
+async fn with_timeout<F>(limit: Option<Duration>, send: F) -> Result<Response, SendError>
+where
+    F: Future<Output = Result<Response, SendError>>,
+{
+    match limit {
+        Some(limit) => timeout(limit, send).await.map_err(SendError::Timeout)?,
+        None => send.await,
+    }
+}
+
+async fn send_over<S>(stream: S, request: Request, host: HeaderValue) -> Result<Response, SendError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let uri = match request.url().query().filter(|q| !q.is_empty()) {
+        Some(query) => format!("{}?{}", request.url().path(), query),
+        None => request.url().path().to_owned(),
+    };
+    let mut builder = hyper::Request::builder()
+        .method(request.method().clone())
+        .uri(uri);
+    if let Some(headers) = builder.headers_mut() {
+        headers.extend(request.headers().clone());
+        headers.insert(HOST, host);
+    }
+    // Request bodies are always buffered, never streamed.
+    let body = request
+        .body()
+        .and_then(Body::as_bytes)
+        .map(|bytes| HyperBody::from(bytes.to_vec()))
+        .unwrap_or_else(HyperBody::empty);
+    let request = builder.body(body).map_err(SendError::Build)?;
+
+    let (mut sender, connection) = hyper::client::conn::handshake(stream)
+        .await
+        .map_err(SendError::Request)?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(SendError::Request)?;
+    Ok(response.map(Body::wrap_stream).into())
+}
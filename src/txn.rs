@@ -0,0 +1,205 @@
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+
+use crate::errors::{Error, Result};
+use crate::kv::{base64_value, KVPair};
+use crate::request::{put, Body};
+use crate::{Client, WriteMeta, WriteOptions};
+
+/// https://www.consul.io/api/txn.html#kv-operations
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum KVTxnVerb {
+    Set,
+    Cas,
+    Lock,
+    Unlock,
+    Get,
+    GetTree,
+    CheckIndex,
+    CheckSession,
+    CheckNotExists,
+    Delete,
+    DeleteTree,
+    DeleteCas,
+}
+
+#[derive(Clone, Eq, PartialEq, Serialize, Debug)]
+pub struct KVTxnOp {
+    pub Verb: KVTxnVerb,
+    pub Key: String,
+    #[serde(
+        serialize_with = "base64_value::serialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub Value: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Flags: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Index: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub Session: Option<String>,
+}
+
+impl KVTxnOp {
+    pub fn new(verb: KVTxnVerb, key: &str) -> KVTxnOp {
+        KVTxnOp {
+            Verb: verb,
+            Key: key.to_owned(),
+            Value: None,
+            Flags: None,
+            Index: None,
+            Session: None,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Serialize, Debug)]
+pub enum TxnOp {
+    KV(KVTxnOp),
+}
+
+impl TxnOp {
+    pub fn kv_set(key: &str, value: &[u8]) -> TxnOp {
+        TxnOp::KV(KVTxnOp {
+            Value: Some(value.to_vec()),
+            ..KVTxnOp::new(KVTxnVerb::Set, key)
+        })
+    }
+
+    /// Sets the key only if its `ModifyIndex` still matches `index`; 0 only creates it.
+    pub fn kv_cas(key: &str, value: &[u8], index: u64) -> TxnOp {
+        TxnOp::KV(KVTxnOp {
+            Value: Some(value.to_vec()),
+            Index: Some(index),
+            ..KVTxnOp::new(KVTxnVerb::Cas, key)
+        })
+    }
+
+    pub fn kv_lock(key: &str, value: &[u8], session: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp {
+            Value: Some(value.to_vec()),
+            Session: Some(session.to_owned()),
+            ..KVTxnOp::new(KVTxnVerb::Lock, key)
+        })
+    }
+
+    pub fn kv_unlock(key: &str, value: &[u8], session: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp {
+            Value: Some(value.to_vec()),
+            Session: Some(session.to_owned()),
+            ..KVTxnOp::new(KVTxnVerb::Unlock, key)
+        })
+    }
+
+    pub fn kv_get(key: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp::new(KVTxnVerb::Get, key))
+    }
+
+    pub fn kv_get_tree(prefix: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp::new(KVTxnVerb::GetTree, prefix))
+    }
+
+    pub fn kv_check_index(key: &str, index: u64) -> TxnOp {
+        TxnOp::KV(KVTxnOp {
+            Index: Some(index),
+            ..KVTxnOp::new(KVTxnVerb::CheckIndex, key)
+        })
+    }
+
+    pub fn kv_check_session(key: &str, session: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp {
+            Session: Some(session.to_owned()),
+            ..KVTxnOp::new(KVTxnVerb::CheckSession, key)
+        })
+    }
+
+    pub fn kv_check_not_exists(key: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp::new(KVTxnVerb::CheckNotExists, key))
+    }
+
+    pub fn kv_delete(key: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp::new(KVTxnVerb::Delete, key))
+    }
+
+    pub fn kv_delete_tree(prefix: &str) -> TxnOp {
+        TxnOp::KV(KVTxnOp::new(KVTxnVerb::DeleteTree, prefix))
+    }
+
+    pub fn kv_delete_cas(key: &str, index: u64) -> TxnOp {
+        TxnOp::KV(KVTxnOp {
+            Index: Some(index),
+            ..KVTxnOp::new(KVTxnVerb::DeleteCas, key)
+        })
+    }
+}
+
+/// Result of a single operation, in the same position as the operation in the request.
+#[derive(Clone, Eq, PartialEq, Deserialize, Debug)]
+pub enum TxnResult {
+    KV(KVPair),
+}
+
+/// Why Consul rolled back a transaction, pointing at the operation that failed.
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct TxnError {
+    pub OpIndex: usize,
+    pub What: String,
+}
+
+#[derive(Default, Deserialize, Debug)]
+#[serde(default)]
+struct TxnResponse {
+    Results: Option<Vec<TxnResult>>,
+    Errors: Option<Vec<TxnError>>,
+}
+
+#[async_trait]
+pub trait Txn {
+    async fn txn(
+        &self,
+        ops: &[TxnOp],
+        options: Option<&WriteOptions>,
+    ) -> Result<(Vec<TxnResult>, WriteMeta)>;
+}
+
+#[async_trait]
+impl Txn for Client {
+    /// https://www.consul.io/api/txn.html#create-transaction
+    ///
+    /// A rolled back transaction fails with `Error::TransactionRolledBack`.
+    async fn txn(
+        &self,
+        ops: &[TxnOp],
+        options: Option<&WriteOptions>,
+    ) -> Result<(Vec<TxnResult>, WriteMeta)> {
+        let result: Result<(TxnResponse, WriteMeta)> = put(
+            "/v1/txn",
+            Some(Body::AsJson(ops)),
+            &self.config,
+            HashMap::new(),
+            options,
+        )
+        .await;
+        let (response, meta) = match result {
+            // A rolled back transaction still reports what went wrong in the body.
+            Err(Error::Status { status, body, .. }) if status == StatusCode::CONFLICT => {
+                let response: TxnResponse =
+                    serde_json::from_str(&body).map_err(|e| Error::Decode {
+                        path: String::from("/v1/txn"),
+                        source: e,
+                    })?;
+                return Err(Error::TransactionRolledBack(
+                    response.Errors.unwrap_or_default(),
+                ));
+            }
+            r => r?,
+        };
+        match response.Errors {
+            Some(errors) if !errors.is_empty() => Err(Error::TransactionRolledBack(errors)),
+            _ => Ok((response.Results.unwrap_or_default(), meta)),
+        }
+    }
+}
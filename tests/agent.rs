@@ -0,0 +1,389 @@
+extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
+use consul::agent::{
+    Agent, AgentCheckRegistration, AgentServiceCheck, AgentServiceConnect,
+    AgentServiceRegistration, MemberEvent, MemberStatus, MembersOpts, TtlHeartbeat,
+};
+use consul::health::HealthStatus;
+use consul::{Client, Config};
+use futures::StreamExt;
+use std::time::Duration;
+
+#[test]
+fn agent_service_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let registration = AgentServiceRegistration {
+        ID: String::from("test-web-1"),
+        Name: String::from("test-web"),
+        Tags: vec![String::from("primary")],
+        Port: 8080,
+        Meta: vec![(String::from("version"), String::from("1.0"))]
+            .into_iter()
+            .collect(),
+        Check: Some(AgentServiceCheck {
+            TTL: String::from("30s"),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    rt.block_on(client.service_register(&registration)).unwrap();
+
+    let services = rt.block_on(client.services()).unwrap();
+    let service = services.get("test-web-1").unwrap();
+    assert_eq!(service.Service, "test-web");
+    assert_eq!(service.Port, 8080);
+
+    let (service, _) = rt.block_on(client.service("test-web-1", None)).unwrap();
+    let service = service.unwrap();
+    assert_eq!(service.Meta.get("version").map(String::as_str), Some("1.0"));
+    assert_eq!(service.Tags, Some(vec![String::from("primary")]));
+
+    rt.block_on(client.service_maintenance("test-web-1", true, Some("testing")))
+        .unwrap();
+    let checks = rt.block_on(client.checks()).unwrap();
+    assert!(checks.contains_key("_service_maintenance:test-web-1"));
+    rt.block_on(client.service_maintenance("test-web-1", false, None))
+        .unwrap();
+
+    rt.block_on(client.service_deregister("test-web-1"))
+        .unwrap();
+    let services = rt.block_on(client.services()).unwrap();
+    assert!(!services.contains_key("test-web-1"));
+    let (service, _) = rt.block_on(client.service("test-web-1", None)).unwrap();
+    assert!(service.is_none());
+}
+
+#[test]
+fn agent_service_not_found_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let server = MockServer::start(vec![Response::status(
+        "404 Not Found",
+        "unknown service ID: missing",
+    )]);
+    let client = server.client();
+    let (service, _) = rt.block_on(client.service("missing", None)).unwrap();
+    assert!(service.is_none());
+    assert!(server
+        .request_line()
+        .starts_with("GET /v1/agent/service/missing"));
+}
+
+#[test]
+fn service_registration_encoding_test() {
+    let registration = AgentServiceRegistration {
+        Name: String::from("web"),
+        Check: Some(AgentServiceCheck {
+            HTTP: String::from("http://localhost:8080/health"),
+            Interval: String::from("10s"),
+            ..Default::default()
+        }),
+        Connect: Some(AgentServiceConnect {
+            SidecarService: Some(Box::new(AgentServiceRegistration::default())),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let json = serde_json::to_value(&registration).unwrap();
+    assert_eq!(json["Name"], "web");
+    assert!(json.get("Weights").is_none());
+    assert!(json.get("Proxy").is_none());
+    // Unset durations are left out, as Consul can't parse an empty one.
+    assert_eq!(
+        json["Check"],
+        serde_json::json!({
+            "HTTP": "http://localhost:8080/health",
+            "Interval": "10s",
+            "TLSSkipVerify": false,
+            "GRPCUseTLS": false,
+            "SuccessBeforePassing": 0,
+            "FailuresBeforeCritical": 0,
+        })
+    );
+    assert_eq!(json["Connect"]["SidecarService"]["Name"], "");
+}
+
+#[test]
+fn agent_check_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let registration = AgentCheckRegistration {
+        ID: String::from("test-ttl"),
+        Check: AgentServiceCheck {
+            Name: String::from("test ttl"),
+            ..AgentServiceCheck::ttl(Duration::from_secs(30))
+        },
+        ..Default::default()
+    };
+    rt.block_on(client.check_register(&registration)).unwrap();
+    let status = |rt: &mut Runtime| rt.block_on(client.checks()).unwrap()["test-ttl"].clone();
+    assert_eq!(status(&mut rt).Status, HealthStatus::Critical);
+
+    rt.block_on(client.pass_ttl("test-ttl", Some("ok")))
+        .unwrap();
+    assert_eq!(status(&mut rt).Status, HealthStatus::Passing);
+    rt.block_on(client.warn_ttl("test-ttl", None)).unwrap();
+    assert_eq!(status(&mut rt).Status, HealthStatus::Warning);
+    rt.block_on(client.update_ttl("test-ttl", "disk full", HealthStatus::Critical))
+        .unwrap();
+    let check = status(&mut rt);
+    assert_eq!(check.Status, HealthStatus::Critical);
+    assert_eq!(check.Output, "disk full");
+
+    // The heartbeat reports the probe's result until it is stopped.
+    rt.block_on(async {
+        let heartbeat =
+            TtlHeartbeat::spawn(&client, "test-ttl", Duration::from_millis(100), || {
+                futures::future::ready(Err::<(), _>("probe failed"))
+            });
+        tokio::time::delay_for(Duration::from_millis(300)).await;
+        heartbeat.stop();
+    });
+    assert_eq!(status(&mut rt).Output, "probe failed");
+    rt.block_on(async {
+        let _heartbeat =
+            TtlHeartbeat::spawn(&client, "test-ttl", Duration::from_millis(100), || {
+                futures::future::ready(Ok::<(), String>(()))
+            });
+        tokio::time::delay_for(Duration::from_millis(300)).await;
+    });
+    assert_eq!(status(&mut rt).Status, HealthStatus::Passing);
+
+    rt.block_on(client.check_deregister("test-ttl")).unwrap();
+    assert!(!rt
+        .block_on(client.checks())
+        .unwrap()
+        .contains_key("test-ttl"));
+}
+
+#[test]
+fn ttl_heartbeat_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let server = MockServer::start(vec![Response::ok("")]);
+    let client = server.client();
+    rt.block_on(async {
+        let heartbeat = TtlHeartbeat::spawn(&client, "test-ttl", Duration::from_secs(60), || {
+            futures::future::ready(Err::<(), _>("probe failed"))
+        });
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        heartbeat.stop();
+    });
+    let request = server.request();
+    assert!(request.starts_with("PUT /v1/agent/check/update/test-ttl"));
+    assert!(request.contains(r#""Status":"critical""#));
+    assert!(request.contains(r#""Output":"probe failed""#));
+}
+
+#[test]
+fn check_registration_encoding_test() {
+    let registration = AgentCheckRegistration {
+        ID: String::from("web-health"),
+        ServiceID: String::from("web"),
+        Check: AgentServiceCheck {
+            Name: String::from("web health"),
+            ..AgentServiceCheck::http("http://localhost:8080/health", Duration::from_millis(1500))
+        },
+        ..Default::default()
+    };
+    let json = serde_json::to_value(&registration).unwrap();
+    assert_eq!(json["ID"], "web-health");
+    assert_eq!(json["ServiceID"], "web");
+    assert_eq!(json["Name"], "web health");
+    assert_eq!(json["HTTP"], "http://localhost:8080/health");
+    assert_eq!(json["Interval"], "1500ms");
+    assert!(json.get("Namespace").is_none());
+
+    assert_eq!(
+        AgentServiceCheck::tcp("localhost:22", Duration::from_secs(10)).Interval,
+        "10s"
+    );
+    assert_eq!(AgentServiceCheck::ttl(Duration::from_secs(30)).TTL, "30s");
+    assert_eq!(
+        AgentServiceCheck::grpc("localhost:9090/health", Duration::from_secs(5)).GRPC,
+        "localhost:9090/health"
+    );
+    assert_eq!(
+        AgentServiceCheck::script(&["/bin/check", "-v"], Duration::from_secs(5)).Args,
+        vec!["/bin/check", "-v"]
+    );
+    assert_eq!(AgentServiceCheck::alias("web").AliasService, "web");
+}
+
+#[test]
+fn agent_members_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+    let members = rt.block_on(client.members(false)).unwrap();
+    assert!(members.iter().any(|m| m.status() == MemberStatus::Alive));
+
+    let opts = MembersOpts {
+        segment: Some(String::from("_all")),
+        ..Default::default()
+    };
+    let all = rt.block_on(client.members_opts(&opts)).unwrap();
+    assert!(all.len() >= members.len());
+
+    let mut watch = client.watch_members(&MembersOpts::default(), Duration::from_secs(1));
+    match rt.block_on(watch.next()).unwrap().unwrap() {
+        MemberEvent::Joined(member) => assert!(members.iter().any(|m| m.Name == member.Name)),
+        event => panic!("Expected a join, got {:?}", event),
+    }
+}
+
+#[test]
+fn watch_members_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let server = MockServer::start(vec![
+        Response::ok(r#"[{"Name":"a","Status":1},{"Name":"b","Status":1}]"#),
+        Response::ok(r#"[{"Name":"a","Status":4},{"Name":"c","Status":1}]"#),
+    ]);
+    let client = server.client();
+    let watch = client.watch_members(&MembersOpts::default(), Duration::from_millis(10));
+    let mut events: Vec<(String, &str)> = rt
+        .block_on(watch.take(5).collect::<Vec<_>>())
+        .into_iter()
+        .map(|event| match event.unwrap() {
+            MemberEvent::Joined(m) => (m.Name, "joined"),
+            MemberEvent::Updated(m) => (m.Name, "updated"),
+            MemberEvent::Left(m) => (m.Name, "left"),
+        })
+        .collect();
+    events.sort();
+    assert_eq!(
+        events,
+        vec![
+            (String::from("a"), "joined"),
+            (String::from("a"), "left"),
+            (String::from("b"), "joined"),
+            (String::from("b"), "left"),
+            (String::from("c"), "joined"),
+        ]
+    );
+}
+
+#[test]
+fn agent_info_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let info = rt.block_on(client.self_info()).unwrap();
+    assert_eq!(info.Config.Datacenter, "dc1");
+    assert_eq!(info.Member.Name, info.Config.NodeName);
+    assert!(info.Stats.agent.contains_key("services"));
+
+    let metrics = rt.block_on(client.metrics()).unwrap();
+    assert!(!metrics.Timestamp.is_empty());
+}
+
+// `/v1/agent/host` and `/v1/agent/version` are newer than the agent CI runs against.
+#[test]
+fn agent_info_decoding_test() {
+    use consul::agent::AgentSelf;
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let info: AgentSelf = serde_json::from_str(
+        r#"{
+            "Config": {"Datacenter": "dc1", "NodeName": "node-1", "Server": true, "Version": "1.9.4"},
+            "DebugConfig": {"Bootstrap": false},
+            "Coord": {"Vec": [0.0, 0.1], "Error": 1.5, "Adjustment": 0.0, "Height": 1e-05},
+            "Member": {"Name": "node-1", "Addr": "10.0.0.1", "Port": 8301, "Status": 1},
+            "Stats": {"agent": {"services": "1"}, "serf_lan": {"members": "3"}},
+            "Meta": {"consul-network-segment": ""}
+        }"#,
+    )
+    .unwrap();
+    assert!(info.Config.Server);
+    assert_eq!(info.Member.status(), MemberStatus::Alive);
+    assert_eq!(info.Stats.serf_lan["members"], "3");
+    assert_eq!(info.Coord.unwrap().Vec, vec![0.0, 0.1]);
+
+    let server = MockServer::start(vec![
+        Response::ok(
+            r#"{
+                "Memory": {"total": 8000, "available": 6000, "used": 2000, "usedPercent": 25.0},
+                "CPU": [{"cpu": 0, "vendorId": "GenuineIntel", "modelName": "Xeon", "mhz": 2400.0}],
+                "Host": {"hostname": "node-1", "bootTime": 1600000000, "hostId": "abc"},
+                "Disk": {"path": "/consul/data", "total": 100, "usedPercent": 10.5},
+                "CollectionTime": 1600000000000000000,
+                "Errors": null
+            }"#,
+        ),
+        Response::ok(
+            r#"{"SHA": "", "BuildDate": "2021-02-17T00:00:00Z", "HumanVersion": "1.9.4", "FIPS": ""}"#,
+        ),
+    ]);
+    let client = server.client();
+    let host = rt.block_on(client.host()).unwrap();
+    assert!(server.request_line().starts_with("GET /v1/agent/host"));
+    assert_eq!(host.Memory.used_percent, 25.0);
+    assert_eq!(host.CPU[0].vendor_id, "GenuineIntel");
+    assert_eq!(host.Host.host_id, "abc");
+    assert_eq!(host.Disk.path, "/consul/data");
+
+    let version = rt.block_on(client.version()).unwrap();
+    assert!(server.request_line().starts_with("GET /v1/agent/version"));
+    assert_eq!(version.HumanVersion, "1.9.4");
+}
+
+#[test]
+fn monitor_test() {
+    use consul::agent::LogLevel;
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    // Lines are split across chunks, and the agent keeps logging until the client goes away.
+    let server = MockServer::start(vec![Response::endless(
+        &["[INFO] agent: started\n[WA", "RN] agent: slow\r\n"],
+        "[INFO] agent: tick\n",
+    )]);
+    let client = server.client();
+    let lines = rt.block_on(async {
+        let mut monitor = client.monitor(LogLevel::Warn, true);
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            lines.push(monitor.next().await.unwrap().unwrap());
+        }
+        drop(monitor);
+        // Give the connection task a chance to shut down.
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        lines
+    });
+    assert_eq!(
+        lines,
+        vec![
+            "[INFO] agent: started",
+            "[WARN] agent: slow",
+            "[INFO] agent: tick"
+        ]
+    );
+
+    let request_line = server.request_line();
+    assert!(request_line.starts_with("GET /v1/agent/monitor?"));
+    assert!(request_line.contains("loglevel=warn"));
+    assert!(request_line.contains("logjson=true"));
+    // Dropping the stream closed the connection.
+    assert!(server.wait_closed());
+}
@@ -1,4 +1,9 @@
 extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
+use consul::catalog::{CatalogDeregistration, CatalogRegistration, CatalogRegistrationService};
+use consul::health::{HealthCheck, HealthCheckDefinition, HealthStatus};
 use consul::{Client, Config};
 
 #[test]
@@ -28,3 +33,180 @@ fn ds_services_test() {
         Some(val) => assert_eq!(val.len(), 0), // consul has no tags
     }
 }
+
+fn external_registration() -> CatalogRegistration {
+    CatalogRegistration {
+        Node: String::from("google"),
+        Address: String::from("www.google.com"),
+        NodeMeta: vec![(String::from("external-node"), String::from("true"))]
+            .into_iter()
+            .collect(),
+        Service: Some(CatalogRegistrationService {
+            ID: String::from("gsearch-1"),
+            Service: String::from("gsearch"),
+            Port: 80,
+            Tags: vec![String::from("release")],
+            ..Default::default()
+        }),
+        Checks: vec![
+            HealthCheck {
+                CheckID: String::from("google-ping"),
+                Name: String::from("Node reachable"),
+                Status: HealthStatus::Passing,
+                ..Default::default()
+            },
+            HealthCheck {
+                CheckID: String::from("gsearch-http"),
+                Name: String::from("Search responds"),
+                Status: HealthStatus::Passing,
+                ServiceID: String::from("gsearch-1"),
+                Definition: HealthCheckDefinition {
+                    HTTP: String::from("http://www.google.com"),
+                    Interval: String::from("30s"),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn catalog_register_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::catalog::Catalog;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let (registered, _) = rt
+        .block_on(client.register(&external_registration(), None))
+        .unwrap();
+    assert!(registered);
+
+    let (nodes, _) = rt.block_on(client.nodes(None)).unwrap();
+    let node = nodes.iter().find(|n| n.Node == "google").unwrap();
+    assert_eq!(node.Address, "www.google.com");
+    assert_eq!(
+        node.Meta.get("external-node").map(String::as_str),
+        Some("true")
+    );
+    let (services, _) = rt.block_on(client.services(None)).unwrap();
+    assert_eq!(services.get("gsearch").unwrap(), &["release"]);
+
+    let (instances, _) = rt
+        .block_on(client.service("gsearch", Some("release"), None))
+        .unwrap();
+    assert_eq!(instances.len(), 1);
+    assert_eq!(instances[0].Node, "google");
+    assert_eq!(instances[0].ServiceID, "gsearch-1");
+    assert_eq!(instances[0].ServicePort, 80);
+    let (instances, _) = rt
+        .block_on(client.service("gsearch", Some("beta"), None))
+        .unwrap();
+    assert!(instances.is_empty());
+
+    let (node, _) = rt.block_on(client.node("google", None)).unwrap();
+    assert!(node.unwrap().Services.contains_key("gsearch-1"));
+
+    let dereg = CatalogDeregistration {
+        Node: String::from("google"),
+        ..Default::default()
+    };
+    let (deregistered, _) = rt.block_on(client.deregister(&dereg, None)).unwrap();
+    assert!(deregistered);
+    let (nodes, _) = rt.block_on(client.nodes(None)).unwrap();
+    assert!(nodes.iter().all(|n| n.Node != "google"));
+}
+
+#[test]
+fn catalog_registration_encoding_test() {
+    let json = serde_json::to_value(external_registration()).unwrap();
+    assert_eq!(json["NodeMeta"]["external-node"], "true");
+    assert_eq!(json["Service"]["Service"], "gsearch");
+    for read_only in &["CreateIndex", "ModifyIndex", "ContentHash", "Datacenter"] {
+        assert!(json["Service"].get(read_only).is_none());
+    }
+    assert!(json["Service"].get("Weights").is_none());
+    assert!(json.get("Check").is_none());
+    assert_eq!(json["Checks"][0]["CheckID"], "google-ping");
+    assert_eq!(
+        json["Checks"][1]["Definition"]["HTTP"],
+        "http://www.google.com"
+    );
+    assert!(json["Checks"][1]["Definition"].get("TCP").is_none());
+    assert!(json.get("Partition").is_none());
+
+    let node: consul::catalog::Node = serde_json::from_str(
+        r#"{"Node": "google", "Address": "www.google.com", "TaggedAddresses": null, "Meta": null}"#,
+    )
+    .unwrap();
+    assert!(node.Meta.is_empty());
+}
+
+#[test]
+fn catalog_lookup_test() {
+    use consul::catalog::Catalog;
+    use consul::QueryOptions;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let server = MockServer::start(vec![
+        Response::ok(
+            r#"[{"Node":"google","ServiceID":"gsearch-1","ServiceName":"gsearch","ServiceTags":null,"ServicePort":80}]"#,
+        )
+        .header("X-Consul-Index", 7),
+        Response::ok("null"),
+        Response::status("404 Not Found", "Node not found"),
+        Response::ok(
+            r#"{"Node":{"Node":"google","Address":"www.google.com"},"Services":[{"ID":"gsearch-1","Service":"gsearch","Port":80}]}"#,
+        ),
+        Response::ok(
+            r#"[{"Gateway":{"Name":"ingress"},"Service":{"Name":"web"},"GatewayKind":"ingress-gateway","Port":8080,"Hosts":null}]"#,
+        ),
+    ]);
+    let client = server.client();
+    let q = QueryOptions {
+        wait_index: Some(5),
+        wait_time: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+
+    let (instances, meta) = rt
+        .block_on(client.service("gsearch", Some("release"), Some(&q)))
+        .unwrap();
+    assert_eq!(instances[0].ServiceID, "gsearch-1");
+    assert!(instances[0].ServiceTags.is_empty());
+    assert_eq!(meta.last_index, Some(7));
+    let request = server.request_line();
+    assert!(request.starts_with("GET /v1/catalog/service/gsearch?"));
+    assert!(request.contains("tag=release"));
+    assert!(request.contains("index=5"));
+
+    let (node, _) = rt.block_on(client.node("missing", Some(&q))).unwrap();
+    assert!(node.is_none());
+    let (list, _) = rt.block_on(client.node_services("missing", None)).unwrap();
+    assert!(list.is_none());
+    assert!(server.request_line().contains("/v1/catalog/node/missing"));
+    assert!(server
+        .request_line()
+        .contains("/v1/catalog/node-services/missing"));
+    // Consul added `/v1/catalog/node-services` in 1.7, after the agent CI runs against.
+    let (list, _) = rt.block_on(client.node_services("google", None)).unwrap();
+    assert_eq!(list.unwrap().Services[0].Service, "gsearch");
+    assert!(server
+        .request_line()
+        .starts_with("GET /v1/catalog/node-services/google"));
+
+    let (services, _) = rt
+        .block_on(client.gateway_services("ingress", None))
+        .unwrap();
+    assert_eq!(services[0].Service.Name, "web");
+    assert_eq!(services[0].Port, 8080);
+    assert!(server
+        .request_line()
+        .contains("/v1/catalog/gateway-services/ingress"));
+}
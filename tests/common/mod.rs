@@ -0,0 +1,224 @@
+//! A mock Consul agent answering canned responses, for tests that don't need a real agent.
+#![allow(dead_code)]
+
+use consul::{Client, Config};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A canned response.
+pub struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Body,
+}
+
+enum Body {
+    Full(String),
+    /// Sent in chunks, then `repeat` until the client goes away.
+    Endless {
+        chunks: Vec<String>,
+        repeat: String,
+    },
+}
+
+impl Response {
+    pub fn ok(body: &str) -> Response {
+        Response::status("200 OK", body)
+    }
+
+    pub fn status(status: &'static str, body: &str) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Body::Full(body.to_owned()),
+        }
+    }
+
+    /// A chunked body sending `chunks`, then `repeat` every 20ms until the client
+    /// disconnects, like the agent's log stream.
+    pub fn endless(chunks: &[&str], repeat: &str) -> Response {
+        Response {
+            status: "200 OK",
+            headers: Vec::new(),
+            body: Body::Endless {
+                chunks: chunks.iter().map(|c| String::from(*c)).collect(),
+                repeat: repeat.to_owned(),
+            },
+        }
+    }
+
+    pub fn header<V: ToString>(mut self, name: &'static str, value: V) -> Response {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// Answers one connection per response, in order.
+pub struct MockServer {
+    pub address: String,
+    requests: Receiver<String>,
+    served: Arc<AtomicUsize>,
+    closed: Receiver<()>,
+}
+
+impl MockServer {
+    pub fn start(responses: Vec<Response>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        MockServer::spawn(address, responses, move || listener.accept().unwrap().0)
+    }
+
+    /// Serves on a fresh unix socket named after `name`.
+    #[cfg(unix)]
+    pub fn start_unix(name: &str, responses: Vec<Response>) -> MockServer {
+        use std::os::unix::net::UnixListener;
+
+        let socket =
+            std::env::temp_dir().join(format!("consul-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let address = format!("unix://{}", socket.display());
+        MockServer::spawn(address, responses, move || listener.accept().unwrap().0)
+    }
+
+    /// Serves HTTPS at `127.0.0.1` with the `server.dc1.consul` certificate of `data/tls`,
+    /// which has no IP address among its names.
+    pub fn start_tls(responses: Vec<Response>) -> MockServer {
+        use native_tls::{Identity, TlsAcceptor};
+
+        let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("data/tls");
+        let cert = std::fs::read(data.join("server.pem")).unwrap();
+        let key = std::fs::read(data.join("server-key.pem")).unwrap();
+        let acceptor = TlsAcceptor::new(Identity::from_pkcs8(&cert, &key).unwrap()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("https://{}", listener.local_addr().unwrap());
+        MockServer::spawn(address, responses, move || loop {
+            // Clients rejecting the certificate end the handshake.
+            if let Ok(stream) = acceptor.accept(listener.accept().unwrap().0) {
+                return stream;
+            }
+        })
+    }
+
+    fn spawn<S, F>(address: String, responses: Vec<Response>, mut accept: F) -> MockServer
+    where
+        S: Read + Write + Send + 'static,
+        F: FnMut() -> S + Send + 'static,
+    {
+        let (sender, requests) = mpsc::channel();
+        let (closed_sender, closed) = mpsc::channel();
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+        std::thread::spawn(move || {
+            for response in responses {
+                let mut stream = accept();
+                let request = read_request(&mut stream);
+                counter.fetch_add(1, Ordering::SeqCst);
+                // The test may not look at the request.
+                let _ = sender.send(request);
+                match response.body {
+                    // Streamed alongside the following connections, like a blocking query.
+                    Body::Endless { .. } => {
+                        let closed_sender = closed_sender.clone();
+                        std::thread::spawn(move || respond(&mut stream, response, &closed_sender));
+                    }
+                    Body::Full(_) => respond(&mut stream, response, &closed_sender),
+                }
+            }
+        });
+        MockServer {
+            address,
+            requests,
+            served,
+            closed,
+        }
+    }
+
+    pub fn client(&self) -> Client {
+        let mut config = Config::new().unwrap();
+        config.address = self.address.clone();
+        Client::new(config)
+    }
+
+    /// The next request received, head and body.
+    pub fn request(&self) -> String {
+        self.requests.recv_timeout(TIMEOUT).unwrap()
+    }
+
+    pub fn request_line(&self) -> String {
+        self.request().lines().next().unwrap_or("").to_owned()
+    }
+
+    /// Number of requests received so far.
+    pub fn served(&self) -> usize {
+        self.served.load(Ordering::SeqCst)
+    }
+
+    /// Whether the client closed an endless response.
+    pub fn wait_closed(&self) -> bool {
+        self.closed.recv_timeout(TIMEOUT).is_ok()
+    }
+}
+
+fn respond<S: Write>(stream: &mut S, response: Response, closed: &Sender<()>) {
+    let mut head = format!("HTTP/1.1 {}\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    match response.body {
+        Body::Full(body) => {
+            let response = format!(
+                "{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                head,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+        Body::Endless { chunks, repeat } => {
+            head.push_str("Transfer-Encoding: chunked\r\n\r\n");
+            let _ = stream.write_all(head.as_bytes());
+            for chunk in chunks.iter().chain(std::iter::repeat(&repeat)) {
+                let framed = format!("{:x}\r\n{}\r\n", chunk.len(), chunk);
+                if stream.write_all(framed.as_bytes()).is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            let _ = closed.send(());
+        }
+    }
+}
+
+/// Reads the request head and as much of the body as its `Content-Length` announces.
+fn read_request<S: Read>(stream: &mut S) -> String {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let read = stream.read(&mut buf).unwrap_or(0);
+        request.extend_from_slice(&buf[..read]);
+        let text = String::from_utf8_lossy(&request).into_owned();
+        if read == 0 {
+            return text;
+        }
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text
+                .lines()
+                .find_map(|l| {
+                    l.to_lowercase()
+                        .strip_prefix("content-length: ")
+                        .map(String::from)
+                })
+                .map_or(0, |l| l.parse().unwrap());
+            if request.len() >= end + 4 + length {
+                return text;
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
+use consul::errors::Error;
+use consul::{Client, Config, ConfigBuilder, HttpBasicAuth, QueryOptions};
+use std::time::Duration;
+
+#[test]
+fn scope_and_auth_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let server = MockServer::start(vec![Response::ok("[]")]);
+    let mut config = Config::new().unwrap();
+    config.address = server.address.clone();
+    config.datacenter = Some(String::from("dc1"));
+    config.namespace = Some(String::from("team"));
+    config.partition = Some(String::from("part"));
+    config.http_auth = Some(HttpBasicAuth {
+        username: String::from("user"),
+        password: Some(String::from("pass")),
+    });
+    let client = Client::new(config);
+    let options = QueryOptions {
+        namespace: Some(String::from("other")),
+        ..Default::default()
+    };
+    rt.block_on(client.list("", Some(&options))).unwrap();
+
+    let request = server.request();
+    let request_line = request.lines().next().unwrap();
+    assert!(request_line.contains("dc=dc1"));
+    assert!(request_line.contains("ns=other"));
+    assert!(request_line.contains("partition=part"));
+    // base64("user:pass")
+    assert!(request
+        .to_lowercase()
+        .contains("authorization: basic dxnlcjpwyxnz"));
+}
+
+#[test]
+fn builder_test() {
+    let config = Config::builder()
+        .address("https://127.0.0.1:8501")
+        .datacenter("dc1")
+        .token("secret")
+        .wait_time(Duration::from_secs(30))
+        .timeout(Duration::from_secs(60))
+        .connect_timeout(Duration::from_secs(1))
+        .user_agent("consul-test")
+        .build()
+        .unwrap();
+    assert_eq!(config.address, "https://127.0.0.1:8501");
+    assert_eq!(config.datacenter.as_deref(), Some("dc1"));
+    assert_eq!(config.wait_time, Some(Duration::from_secs(30)));
+
+    assert!(ConfigBuilder::new()
+        .address("unix:///var/run/consul.sock")
+        .build()
+        .is_ok());
+
+    for address in &["not a url", "ftp://127.0.0.1:8500", "unix://"] {
+        match Config::builder().address(*address).build().unwrap_err() {
+            Error::InvalidAddress { address: a, .. } => assert_eq!(&a, address),
+            err => panic!("Expected an invalid address error, got {}", err),
+        }
+    }
+
+    match Config::builder()
+        .wait_time(Duration::from_secs(60))
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_err()
+    {
+        Error::InvalidArgument(_) => {}
+        err => panic!("Expected an invalid argument error, got {}", err),
+    }
+}
+
+#[test]
+fn default_wait_time_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let server = MockServer::start(vec![Response::ok("[]")]);
+    let config = Config::builder()
+        .address(&server.address)
+        .wait_time(Duration::from_millis(1500))
+        .build()
+        .unwrap();
+    let client = Client::new(config);
+    let options = QueryOptions {
+        wait_index: Some(10),
+        ..Default::default()
+    };
+    rt.block_on(client.list("", Some(&options))).unwrap();
+
+    let request = server.request();
+    let request_line = request.lines().next().unwrap();
+    assert!(request_line.contains("index=10"));
+    assert!(request_line.contains("wait=1500ms"));
+}
+
+#[test]
+fn query_params_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::catalog::Catalog;
+    use consul::filter::{Filter, Selector};
+    let server = MockServer::start(vec![Response::ok("[]")]);
+    let client = server.client();
+    let options = QueryOptions {
+        filter: Some(Filter::contains(Selector::new("ServiceTags"), "primary").into()),
+        near: Some(String::from("_agent")),
+        node_meta: vec![
+            (String::from("rack"), String::from("r1")),
+            (String::from("env"), String::from("prod")),
+        ]
+        .into_iter()
+        .collect(),
+        allow_stale: true,
+        use_cache: true,
+        max_age: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+    rt.block_on(client.service("web", None, Some(&options)))
+        .unwrap();
+
+    let request = server.request();
+    let request_line = request.lines().next().unwrap();
+    assert!(request_line.contains("filter=ServiceTags+contains+%22primary%22"));
+    assert!(request_line.contains("near=_agent"));
+    assert!(request_line.contains("node-meta=rack%3Ar1"));
+    assert!(request_line.contains("node-meta=env%3Aprod"));
+    assert!(request_line.contains("stale="));
+    assert!(!request_line.contains("consistent"));
+    assert!(request_line.contains("cached="));
+    assert!(request.to_lowercase().contains("cache-control: max-age=30"));
+
+    let options = QueryOptions {
+        require_consistent: true,
+        allow_stale: true,
+        ..Default::default()
+    };
+    match rt.block_on(client.nodes(Some(&options))).unwrap_err() {
+        Error::InvalidArgument(_) => {}
+        err => panic!("Expected an invalid argument error, got {}", err),
+    }
+}
+
+#[test]
+fn query_meta_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    use consul::ConsistencyMode;
+    let server = MockServer::start(vec![
+        Response::ok("[]")
+            .header("X-Consul-Index", 42)
+            .header("X-Consul-KnownLeader", true)
+            .header("X-Consul-LastContact", 1500)
+            .header("X-Consul-Effective-Consistency", "stale")
+            .header("X-Cache", "HIT")
+            .header("Age", 12)
+            .header("X-Consul-Default-ACL-Policy", "deny")
+            .header("X-Consul-Results-Filtered-By-ACLs", true),
+        Response::ok("[]"),
+        // Informational headers a proxy mangled are ignored.
+        Response::ok("[]")
+            .header("X-Consul-Effective-Consistency", "eventual")
+            .header("X-Cache", "hit, miss")
+            .header("Age", "1.5"),
+        Response::ok("[]").header("X-Consul-LastContact", "soon"),
+        // The status is reported rather than a broken header.
+        Response::status("403 Forbidden", "Permission denied").header("X-Consul-Index", "none"),
+    ]);
+    let client = server.client();
+    let (_, meta) = rt.block_on(client.list("", None)).unwrap();
+    assert_eq!(meta.last_index, Some(42));
+    assert!(meta.known_leader);
+    assert_eq!(meta.last_contact, Duration::from_millis(1500));
+    assert_eq!(meta.consistency, Some(ConsistencyMode::Stale));
+    assert!(meta.cache_hit);
+    assert_eq!(meta.cache_age, Some(Duration::from_secs(12)));
+    assert_eq!(meta.default_acl_policy.as_deref(), Some("deny"));
+    assert!(meta.results_filtered_by_acls);
+
+    let (_, meta) = rt.block_on(client.list("", None)).unwrap();
+    assert!(!meta.known_leader);
+    assert_eq!(meta.consistency, None);
+    assert!(!meta.cache_hit);
+    assert!(!meta.results_filtered_by_acls);
+
+    let (_, meta) = rt.block_on(client.list("", None)).unwrap();
+    assert_eq!(meta.consistency, None);
+    assert!(!meta.cache_hit);
+    assert_eq!(meta.cache_age, None);
+
+    match rt.block_on(client.list("", None)).unwrap_err() {
+        Error::InvalidHeader { header, .. } => assert_eq!(header, "X-Consul-LastContact"),
+        err => panic!("Expected an invalid header error, got {}", err),
+    }
+
+    let err = rt.block_on(client.list("", None)).unwrap_err();
+    assert_eq!(err.status().map(|s| s.as_u16()), Some(403));
+}
+
+// The only test in this file touching the environment, since tests run concurrently.
+#[test]
+fn config_env_test() {
+    use std::env;
+
+    let token_file = env::temp_dir().join(format!("consul-token-{}", std::process::id()));
+    std::fs::write(&token_file, "file-token\n").unwrap();
+    env::set_var("CONSUL_HTTP_ADDR", "unix:///var/run/consul.sock");
+    env::set_var("CONSUL_HTTP_TOKEN", "env-token");
+    env::set_var("CONSUL_HTTP_TOKEN_FILE", &token_file);
+    env::set_var("CONSUL_HTTP_AUTH", "user:pa:ss");
+    env::set_var("CONSUL_NAMESPACE", "team");
+    env::set_var("CONSUL_PARTITION", "part");
+    env::set_var("CONSUL_DATACENTER", "dc2");
+
+    let config = Config::new_from_env().unwrap();
+    assert_eq!(config.address, "unix:///var/run/consul.sock");
+    assert_eq!(config.token.as_deref(), Some("file-token"));
+    assert_eq!(
+        config.http_auth,
+        Some(HttpBasicAuth {
+            username: String::from("user"),
+            password: Some(String::from("pa:ss")),
+        })
+    );
+    assert_eq!(config.namespace.as_deref(), Some("team"));
+    assert_eq!(config.partition.as_deref(), Some("part"));
+    assert_eq!(config.datacenter.as_deref(), Some("dc2"));
+
+    // An empty token file falls back to the token variable.
+    std::fs::write(&token_file, "").unwrap();
+    env::set_var("CONSUL_HTTP_AUTH", "user");
+    let config = Config::new_from_env().unwrap();
+    assert_eq!(config.token.as_deref(), Some("env-token"));
+    assert_eq!(config.http_auth.unwrap().password, None);
+
+    std::fs::remove_file(&token_file).unwrap();
+    match Config::new_from_env().unwrap_err() {
+        Error::TokenFile { path, .. } => assert_eq!(path, token_file),
+        err => panic!("Expected a token file error, got {}", err),
+    }
+}
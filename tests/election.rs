@@ -0,0 +1,32 @@
+extern crate consul;
+use consul::election::Election;
+use consul::{Client, Config};
+
+#[test]
+fn election_test() {
+    use futures::StreamExt;
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let election = Election::new(&client, "testelection");
+    assert!(rt.block_on(election.current_leader()).unwrap().is_none());
+
+    let leadership = rt.block_on(election.campaign(b"worker-1")).unwrap();
+    let leader = rt.block_on(election.current_leader()).unwrap().unwrap();
+    assert_eq!(leader.session, leadership.session());
+    assert_eq!(leader.value.as_deref(), Some(&b"worker-1"[..]));
+
+    let mut changes = election.observe();
+    let observed = rt.block_on(changes.next()).unwrap().unwrap();
+    assert_eq!(observed, Some(leader));
+
+    rt.block_on(leadership.resign()).unwrap();
+    let observed = rt.block_on(changes.next()).unwrap().unwrap();
+    assert_eq!(observed, None);
+
+    rt.block_on(client.delete("testelection", None)).unwrap();
+}
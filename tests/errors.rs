@@ -0,0 +1,116 @@
+extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
+use consul::errors::Error;
+use consul::{Client, Config};
+
+#[test]
+fn invalid_url_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::catalog::Catalog;
+    let mut config = Config::new().unwrap();
+    config.address = String::from("not a url");
+    let client = Client::new(config);
+    match rt.block_on(client.datacenters()).unwrap_err() {
+        Error::InvalidUrl { path, .. } => assert_eq!(path, "/v1/catalog/datacenters"),
+        err => panic!("Expected an invalid URL error, got {}", err),
+    }
+}
+
+#[test]
+fn connection_refused_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let mut config = Config::new().unwrap();
+    // Nothing listens on the discard port.
+    config.address = String::from("http://127.0.0.1:9");
+    let client = Client::new(config);
+    let err = rt.block_on(client.list("", None)).unwrap_err();
+    match err {
+        Error::Http { ref path, .. } => assert_eq!(path, "/v1/kv/"),
+        ref err => panic!("Expected an HTTP error, got {}", err),
+    }
+    assert_eq!(err.path(), Some("/v1/kv/"));
+    assert_eq!(err.status(), None);
+}
+
+#[test]
+fn status_error_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let server = MockServer::start(vec![Response::status("403 Forbidden", "Permission denied")]);
+    let client = server.client();
+    match rt.block_on(client.list("secret/", None)).unwrap_err() {
+        Error::Status { path, status, body } => {
+            assert_eq!(path, "/v1/kv/secret/");
+            assert_eq!(status.as_u16(), 403);
+            assert_eq!(body, "Permission denied");
+        }
+        err => panic!("Expected a status error, got {}", err),
+    }
+}
+
+#[test]
+fn not_found_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let server = MockServer::start(vec![Response::status("404 Not Found", "")]);
+    let client = server.client();
+    let (pair, _) = rt.block_on(client.get("missing", None)).unwrap();
+    assert!(pair.is_none());
+}
+
+#[test]
+fn retry_read_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let server = MockServer::start(vec![
+        Response::status("503 Service Unavailable", "No cluster leader"),
+        Response::ok("[]"),
+    ]);
+    let client = server.client();
+    let (pairs, _) = rt.block_on(client.list("retry/", None)).unwrap();
+    assert!(pairs.is_empty());
+    assert_eq!(server.served(), 2);
+}
+
+#[test]
+fn no_retry_for_plain_write_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::{KVPair, KV};
+    let server = MockServer::start(vec![
+        Response::status("503 Service Unavailable", "No cluster leader"),
+        Response::status("503 Service Unavailable", "No cluster leader"),
+        Response::ok("true"),
+    ]);
+    let client = server.client();
+    let pair = KVPair {
+        Key: String::from("retry/key"),
+        Value: Some(b"value".to_vec()),
+        ..Default::default()
+    };
+    let err = rt.block_on(client.put(&pair, None)).unwrap_err();
+    assert_eq!(err.status().map(|s| s.as_u16()), Some(503));
+    assert_eq!(server.served(), 1);
+
+    // The same write guarded by an index is retried.
+    let pair = KVPair {
+        ModifyIndex: Some(0),
+        ..pair
+    };
+    assert!(rt.block_on(client.cas(&pair, None)).unwrap().0);
+    assert_eq!(server.served(), 3);
+}
@@ -0,0 +1,29 @@
+extern crate consul;
+use consul::filter::{Filter, Selector};
+
+#[test]
+fn filter_expression_test() {
+    let tag = Filter::contains(Selector::new("Service.Tags"), "primary");
+    assert_eq!(tag.to_string(), r#"Service.Tags contains "primary""#);
+
+    let meta = Filter::eq(Selector::new("NodeMeta").key("rack-id"), r#"a"b\c"#);
+    assert_eq!(meta.to_string(), r#"NodeMeta["rack-id"] == "a\"b\\c""#);
+
+    let either = tag
+        .clone()
+        .or(Filter::is_empty(Selector::new("Service.Tags")));
+    assert_eq!(
+        either.clone().and(meta.clone()).to_string(),
+        r#"(Service.Tags contains "primary" or Service.Tags is empty) and NodeMeta["rack-id"] == "a\"b\\c""#
+    );
+    assert_eq!(
+        either.not().to_string(),
+        r#"not (Service.Tags contains "primary" or Service.Tags is empty)"#
+    );
+    assert_eq!(
+        tag.and(meta)
+            .or(Filter::matches(Selector::new("Node"), "^web-"))
+            .to_string(),
+        r#"Service.Tags contains "primary" and NodeMeta["rack-id"] == "a\"b\\c" or Node matches "^web-""#
+    );
+}
@@ -1,4 +1,7 @@
 extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
 use consul::{Client, Config};
 
 #[test]
@@ -29,3 +32,89 @@ fn health_test() {
         assert!(meta.last_index.unwrap() > 0, "index must be positive");
     }
 }
+
+#[test]
+fn health_checks_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::agent::Agent;
+    use consul::health::{Health, HealthStatus};
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+    let node = rt.block_on(client.self_info()).unwrap().Config.NodeName;
+
+    let (checks, _) = rt.block_on(client.node(&node, None)).unwrap();
+    let serf = checks.iter().find(|c| c.CheckID == "serfHealth").unwrap();
+    assert_eq!(serf.Status, HealthStatus::Passing);
+
+    let (passing, _) = rt
+        .block_on(client.state(Some(HealthStatus::Passing), None))
+        .unwrap();
+    assert!(passing.iter().any(|c| c.CheckID == "serfHealth"));
+    let (all, _) = rt.block_on(client.state(None, None)).unwrap();
+    assert!(all.len() >= passing.len());
+
+    let (checks, _) = rt
+        .block_on(Health::checks(&client, "non-existing-service", None))
+        .unwrap();
+    assert!(checks.is_empty());
+}
+
+#[test]
+fn health_status_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::health::{Health, HealthStatus};
+    let server = MockServer::start(vec![
+        Response::ok(
+            r#"[{"CheckID":"serfHealth","Status":"passing"},{"CheckID":"web","Status":"warning"}]"#,
+        ),
+        Response::ok(r#"[{"CheckID":"_node_maintenance","Status":"critical"}]"#),
+        Response::ok(r#"[{"Node":{"Node":"n1"},"Service":{"ID":"web-proxy"},"Checks":[]}]"#),
+        Response::ok("[]"),
+    ]);
+    let client = server.client();
+
+    let (checks, _) = rt.block_on(client.node("n1", None)).unwrap();
+    assert_eq!(checks[1].Status, HealthStatus::Warning);
+    assert_eq!(HealthStatus::aggregate(&checks), HealthStatus::Warning);
+    assert!(server.request_line().contains("/v1/health/node/n1"));
+
+    let (checks, _) = rt.block_on(client.state(None, None)).unwrap();
+    assert_eq!(checks[0].Status, HealthStatus::Critical);
+    assert_eq!(HealthStatus::aggregate(&checks), HealthStatus::Maintenance);
+    assert!(server.request_line().contains("/v1/health/state/any"));
+
+    let (entries, _) = rt
+        .block_on(client.connect("web", Some("v2"), true, None))
+        .unwrap();
+    assert_eq!(entries[0].Service.ID, "web-proxy");
+    let request = server.request_line();
+    assert!(request.contains("/v1/health/connect/web?"));
+    assert!(request.contains("passing=1"));
+    assert!(request.contains("tag=v2"));
+
+    let (entries, _) = rt.block_on(client.ingress("web", false, None)).unwrap();
+    assert!(entries.is_empty());
+    let request = server.request_line();
+    assert!(request.contains("/v1/health/ingress/web"));
+    assert!(!request.contains("passing"));
+
+    assert_eq!(HealthStatus::aggregate(&[]), HealthStatus::Passing);
+    assert_eq!(
+        serde_json::to_string(&HealthStatus::Maintenance).unwrap(),
+        r#""maintenance""#
+    );
+
+    match rt.block_on(Health::state(
+        &client,
+        Some(HealthStatus::Maintenance),
+        None,
+    )) {
+        Err(consul::errors::Error::InvalidArgument(_)) => {}
+        result => panic!("Expected an invalid argument, got {:?}", result),
+    }
+    assert_eq!(server.served(), 4);
+}
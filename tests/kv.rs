@@ -1,6 +1,9 @@
 extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
 use consul::kv::KVPair;
-use consul::{Client, Config};
+use consul::{Client, Config, RetryPolicy};
 
 #[test]
 fn kv_test() {
@@ -15,7 +18,7 @@ fn kv_test() {
 
     let pair = KVPair {
         Key: String::from("testkey"),
-        Value: Some(String::from("testvalue")),
+        Value: Some(b"testvalue".to_vec()),
         ..Default::default()
     };
 
@@ -29,3 +32,238 @@ fn kv_test() {
     let r = rt.block_on(client.list("", None)).unwrap();
     assert!(r.0.is_empty());
 }
+
+#[test]
+fn kv_watch_test() {
+    use futures::StreamExt;
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let pair = KVPair {
+        Key: String::from("watchkey"),
+        Value: Some(b"first".to_vec()),
+        ..Default::default()
+    };
+    assert!(rt.block_on(client.put(&pair, None)).unwrap().0);
+
+    let mut watch = client.watch("watchkey", None);
+    let (first, _) = rt.block_on(watch.next()).unwrap().unwrap();
+    let first = first.unwrap();
+
+    let pair = KVPair {
+        Value: Some(b"second".to_vec()),
+        ..pair
+    };
+    assert!(rt.block_on(client.put(&pair, None)).unwrap().0);
+
+    let (second, _) = rt.block_on(watch.next()).unwrap().unwrap();
+    let second = second.unwrap();
+    assert!(second.ModifyIndex > first.ModifyIndex);
+
+    rt.block_on(client.delete("watchkey", None)).unwrap();
+    let (deleted, _) = rt.block_on(watch.next()).unwrap().unwrap();
+    assert!(deleted.is_none());
+}
+
+#[test]
+fn kv_watch_prefix_recovery_test() {
+    use futures::StreamExt;
+    use std::time::{Duration, Instant};
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let server = MockServer::start(vec![
+        Response::ok(r#"[{"Key":"app/a","ModifyIndex":10}]"#).header("X-Consul-Index", 10),
+        // The index went backwards, e.g. after a snapshot restore.
+        Response::ok(r#"[{"Key":"app/a","ModifyIndex":5}]"#).header("X-Consul-Index", 5),
+        Response::status("500 Internal Server Error", "rpc error"),
+        Response::ok(r#"[{"Key":"app/a","ModifyIndex":12}]"#).header("X-Consul-Index", 12),
+    ]);
+    let mut config = Config::new().unwrap();
+    config.address = server.address.clone();
+    config.retry_policy = RetryPolicy::disabled();
+    let client = Client::new(config);
+    let mut watch = client.watch_prefix("app/", None);
+
+    let (pairs, _) = rt.block_on(watch.next()).unwrap().unwrap();
+    assert_eq!(pairs[0].ModifyIndex, Some(10));
+    assert!(!server.request_line().contains("index="));
+
+    let (pairs, _) = rt.block_on(watch.next()).unwrap().unwrap();
+    assert_eq!(pairs[0].ModifyIndex, Some(5));
+    assert!(server.request_line().contains("index=10"));
+
+    // After the reset the watch doesn't block, and an error doesn't end the stream.
+    assert!(rt.block_on(watch.next()).unwrap().is_err());
+    assert!(!server.request_line().contains("index="));
+
+    let start = Instant::now();
+    let (pairs, _) = rt.block_on(watch.next()).unwrap().unwrap();
+    assert_eq!(pairs[0].ModifyIndex, Some(12));
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert!(!server.request_line().contains("index="));
+}
+
+#[test]
+fn kv_cas_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    // An index of 0 only creates the key.
+    let pair = KVPair {
+        Key: String::from("cas/key"),
+        Value: Some(b"first".to_vec()),
+        ModifyIndex: Some(0),
+        ..Default::default()
+    };
+    assert!(rt.block_on(client.cas(&pair, None)).unwrap().0);
+    assert!(!rt.block_on(client.cas(&pair, None)).unwrap().0);
+
+    let stored = rt.block_on(client.list("cas/key", None)).unwrap().0;
+    let pair = KVPair {
+        Value: Some(b"second".to_vec()),
+        ModifyIndex: stored[0].ModifyIndex,
+        ..pair
+    };
+    assert!(rt.block_on(client.cas(&pair, None)).unwrap().0);
+    // The index is stale now.
+    assert!(!rt.block_on(client.cas(&pair, None)).unwrap().0);
+    assert!(!rt.block_on(client.delete_cas(&pair, None)).unwrap().0);
+
+    let other = KVPair {
+        Key: String::from("cas/other"),
+        ..Default::default()
+    };
+    assert!(rt.block_on(client.put(&other, None)).unwrap().0);
+
+    assert!(rt.block_on(client.delete_tree("cas/", None)).unwrap().0);
+    let r = rt.block_on(client.list("cas/", None)).unwrap();
+    assert!(r.0.is_empty());
+}
+
+#[test]
+fn kv_value_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let binary = vec![0u8, 159, 146, 150, 255];
+    let pair = KVPair {
+        Key: String::from("binarykey"),
+        Value: Some(binary.clone()),
+        ..Default::default()
+    };
+    assert!(rt.block_on(client.put(&pair, None)).unwrap().0);
+
+    // Writing back what was read must not encode the value a second time.
+    let read = rt
+        .block_on(client.get("binarykey", None))
+        .unwrap()
+        .0
+        .unwrap();
+    assert_eq!(read.Value.as_ref(), Some(&binary));
+    assert!(rt.block_on(client.put(&read, None)).unwrap().0);
+
+    let raw = rt.block_on(client.get_raw("binarykey", None)).unwrap().0;
+    assert_eq!(raw, Some(binary));
+    assert!(read.value_str().is_err());
+
+    let missing = rt.block_on(client.get_raw("missingkey", None)).unwrap().0;
+    assert_eq!(missing, None);
+
+    rt.block_on(client.delete("binarykey", None)).unwrap();
+}
+
+#[test]
+fn kv_pair_decoding_test() {
+    let pair: KVPair = serde_json::from_str(
+        r#"{"Key": "config", "Flags": 0, "Value": "eyJwb3J0Ijo4MH0=", "ModifyIndex": 12}"#,
+    )
+    .unwrap();
+    assert_eq!(pair.Value.as_deref(), Some(&b"{\"port\":80}"[..]));
+    assert_eq!(pair.value_str().unwrap(), Some("{\"port\":80}"));
+    let value: serde_json::Value = pair.value_json().unwrap().unwrap();
+    assert_eq!(value["port"], 80);
+
+    let empty: KVPair = serde_json::from_str(r#"{"Key": "empty", "Value": null}"#).unwrap();
+    assert_eq!(empty.Value, None);
+    assert_eq!(
+        serde_json::to_value(&pair).unwrap()["Value"],
+        "eyJwb3J0Ijo4MH0="
+    );
+}
+
+#[test]
+fn kv_json_test() {
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Backend {
+        host: String,
+        port: u16,
+    }
+
+    use consul::kv::KV;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let primary = Backend {
+        host: String::from("10.0.0.1"),
+        port: 8080,
+    };
+    assert!(
+        rt.block_on(client.put_json("json/primary", &primary, None))
+            .unwrap()
+            .0
+    );
+    let read: Option<Backend> = rt
+        .block_on(client.get_json("json/primary", None))
+        .unwrap()
+        .0;
+    assert_eq!(read.as_ref(), Some(&primary));
+
+    let missing: Option<Backend> = rt
+        .block_on(client.get_json("json/missing", None))
+        .unwrap()
+        .0;
+    assert!(missing.is_none());
+
+    let all: HashMap<String, Backend> = rt.block_on(client.list_json("json/", None)).unwrap().0;
+    assert_eq!(all.get("json/primary"), Some(&primary));
+
+    let broken = KVPair {
+        Key: String::from("json/broken"),
+        Value: Some(b"not json".to_vec()),
+        ..Default::default()
+    };
+    assert!(rt.block_on(client.put(&broken, None)).unwrap().0);
+    let err = rt
+        .block_on(client.list_json::<Backend>("json/", None))
+        .unwrap_err();
+    assert!(err.to_string().contains("json/broken"));
+
+    rt.block_on(client.delete_tree("json/", None)).unwrap();
+}
+
+#[test]
+fn kv_trait_object_test() {
+    use consul::kv::KV;
+    let client = Client::new(Config::new().unwrap());
+    let kv: Box<dyn KV> = Box::new(client);
+    let _ = kv.watch("key", None);
+}
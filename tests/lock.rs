@@ -0,0 +1,74 @@
+extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
+use consul::lock::{Lock, LockOptions, LOCK_FLAG_VALUE};
+use consul::{Client, Config};
+use std::time::Duration;
+
+#[test]
+fn lock_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let options = LockOptions {
+        value: Some(b"holder".to_vec()),
+        ..LockOptions::new("testlock")
+    };
+    let lock = Lock::new(&client, options.clone());
+    let guard = rt.block_on(lock.acquire()).unwrap();
+    assert!(guard.lock_index().unwrap() > 0);
+    assert!(!guard.is_lost());
+
+    let pair = rt.block_on(client.list("testlock", None)).unwrap().0;
+    assert_eq!(pair[0].Flags, Some(LOCK_FLAG_VALUE));
+    assert_eq!(pair[0].Session.as_deref(), Some(guard.session()));
+
+    // A second contender with its own session can't take the lock while it is held.
+    let other = Lock::new(&client, options.clone());
+    assert!(rt.block_on(other.try_acquire()).unwrap().is_none());
+
+    rt.block_on(guard.release()).unwrap();
+    let pair = rt.block_on(client.list("testlock", None)).unwrap().0;
+    assert_eq!(pair[0].Session, None);
+
+    rt.block_on(client.delete("testlock", None)).unwrap();
+}
+
+#[test]
+fn lock_session_expired_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let held = format!(
+        r#"[{{"Key":"testlock","Flags":{},"Session":"s1","LockIndex":1}}]"#,
+        LOCK_FLAG_VALUE
+    );
+    let server = MockServer::start(vec![
+        Response::ok(r#"{"ID":"s1"}"#),
+        Response::status("404 Not Found", ""),
+        Response::ok("true"),
+        Response::ok(&held).header("X-Consul-Index", 1),
+        // The lock monitor's blocking query, still waiting when the session is renewed.
+        Response::endless(&["["], " "),
+        Response::status("404 Not Found", "Session id 's1' not found"),
+    ]);
+    let options = LockOptions {
+        session_ttl: Duration::from_millis(400),
+        ..LockOptions::new("testlock")
+    };
+    let lock = Lock::new(&server.client(), options);
+    let guard = rt.block_on(lock.acquire()).unwrap();
+
+    // A session that can't be found is given up on at once, not retried for a whole TTL.
+    rt.block_on(async {
+        tokio::time::timeout(Duration::from_secs(1), guard.lost())
+            .await
+            .unwrap();
+    });
+    assert_eq!(server.served(), 6);
+}
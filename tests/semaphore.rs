@@ -0,0 +1,37 @@
+extern crate consul;
+use consul::semaphore::{Semaphore, SemaphoreOptions};
+use consul::{Client, Config};
+
+#[test]
+fn semaphore_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let semaphore = Semaphore::new(&client, SemaphoreOptions::new("testsem", 2));
+    let first = rt.block_on(semaphore.acquire()).unwrap();
+    let second = rt.block_on(semaphore.acquire()).unwrap();
+    assert_ne!(first.session(), second.session());
+
+    // Contender keys are deleted along with a session that dies.
+    use consul::session::Session;
+    let (info, _) = rt.block_on(client.info(first.session(), None)).unwrap();
+    assert_eq!(info[0].Behavior.as_deref(), Some("delete"));
+
+    // Both slots are taken.
+    assert!(rt.block_on(semaphore.try_acquire()).unwrap().is_none());
+
+    rt.block_on(first.release()).unwrap();
+    let third = rt.block_on(semaphore.try_acquire()).unwrap().unwrap();
+    assert!(!third.is_lost());
+
+    rt.block_on(second.release()).unwrap();
+    rt.block_on(third.release()).unwrap();
+
+    let keys = rt.block_on(client.keys("testsem/", None, None)).unwrap().0;
+    assert_eq!(keys, ["testsem/.lock"]);
+    rt.block_on(client.delete("testsem/.lock", None)).unwrap();
+}
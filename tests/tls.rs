@@ -0,0 +1,178 @@
+extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
+use consul::errors::Error;
+use consul::{Client, Config, TlsConfig};
+use std::path::PathBuf;
+
+fn data(file: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("data/tls")
+        .join(file)
+}
+
+#[test]
+fn tls_config_test() {
+    let tls = TlsConfig {
+        ca_file: Some(data("ca/ca.pem")),
+        cert_file: Some(data("client.pem")),
+        key_file: Some(data("client-key.pem")),
+        server_name: Some(String::from("server.dc1.consul")),
+        ..Default::default()
+    };
+    let mut config = Config::new().unwrap();
+    config.address = String::from("https://server.dc1.consul:8501");
+    assert!(config.with_tls(&tls).is_ok());
+
+    let mut config = Config::new().unwrap();
+    config.address = String::from("https://127.0.0.1:8501");
+    assert!(config.with_tls(&tls).is_ok());
+    let mut config = Config::new().unwrap();
+    config.address = String::from("http://127.0.0.1:8500");
+    assert!(config.with_tls(&tls).is_ok());
+
+    let tls = TlsConfig {
+        ca_path: Some(data("ca")),
+        ..Default::default()
+    };
+    assert!(Config::new().unwrap().with_tls(&tls).is_ok());
+}
+
+#[test]
+fn tls_server_name_test() {
+    use consul::kv::KV;
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    let tls_client = |address: &str, server_name: Option<&str>| {
+        let tls = TlsConfig {
+            ca_file: Some(data("ca/ca.pem")),
+            server_name: server_name.map(String::from),
+            ..Default::default()
+        };
+        let mut config = Config::new().unwrap();
+        config.address = address.to_owned();
+        Client::new(config.with_tls(&tls).unwrap())
+    };
+
+    let server = MockServer::start_tls(vec![Response::ok("[]")]);
+    let client = tls_client(&server.address, Some("server.dc1.consul"));
+    rt.block_on(client.list("", None)).unwrap();
+    let request = server.request();
+    assert!(request.starts_with("GET /v1/kv/?recurse"));
+    let host = format!("host: {}", server.address.trim_start_matches("https://"));
+    assert!(request.to_lowercase().contains(&host));
+
+    let server = MockServer::start_tls(vec![Response::ok("[]")]);
+    let client = tls_client(&server.address, Some("server.dc2.consul"));
+    match rt.block_on(client.list("", None)).unwrap_err() {
+        Error::Https { .. } => {}
+        err => panic!("Expected an HTTPS error, got {}", err),
+    }
+
+    // The certificate names no IP address.
+    let server = MockServer::start_tls(vec![Response::ok("[]")]);
+    let client = tls_client(&server.address, None);
+    match rt.block_on(client.list("", None)).unwrap_err() {
+        Error::Http { .. } => {}
+        err => panic!("Expected an HTTP error, got {}", err),
+    }
+}
+
+#[test]
+fn tls_key_format_test() {
+    // PKCS#1, and SEC1 as written by `consul tls cert create`.
+    for (cert, key) in &[
+        ("client.pem", "client-key-pkcs1.pem"),
+        ("dc1-client-consul-0.pem", "dc1-client-consul-0-key.pem"),
+    ] {
+        let tls = TlsConfig {
+            ca_file: Some(data("ca/ca.pem")),
+            cert_file: Some(data(cert)),
+            key_file: Some(data(key)),
+            ..Default::default()
+        };
+        assert!(Config::new().unwrap().with_tls(&tls).is_ok(), "{}", key);
+    }
+}
+
+#[test]
+fn tls_config_error_test() {
+    let tls = TlsConfig {
+        ca_file: Some(data("missing.pem")),
+        ..Default::default()
+    };
+    match Config::new().unwrap().with_tls(&tls).unwrap_err() {
+        Error::TlsMaterial { path, .. } => assert_eq!(path, data("missing.pem")),
+        err => panic!("Expected a TLS material error, got {}", err),
+    }
+
+    // A key is not a certificate.
+    let tls = TlsConfig {
+        ca_file: Some(data("client-key.pem")),
+        ..Default::default()
+    };
+    match Config::new().unwrap().with_tls(&tls).unwrap_err() {
+        Error::TlsMaterial { path, .. } => assert_eq!(path, data("client-key.pem")),
+        err => panic!("Expected a TLS material error, got {}", err),
+    }
+
+    // A certificate is not a key.
+    let tls = TlsConfig {
+        cert_file: Some(data("client.pem")),
+        key_file: Some(data("client.pem")),
+        ..Default::default()
+    };
+    match Config::new().unwrap().with_tls(&tls).unwrap_err() {
+        Error::TlsMaterial { path, .. } => assert_eq!(path, data("client.pem")),
+        err => panic!("Expected a TLS material error, got {}", err),
+    }
+
+    let tls = TlsConfig {
+        cert_file: Some(data("client.pem")),
+        ..Default::default()
+    };
+    match Config::new().unwrap().with_tls(&tls).unwrap_err() {
+        Error::InvalidArgument(_) => {}
+        err => panic!("Expected an invalid argument error, got {}", err),
+    }
+}
+
+// The only test in this file touching the environment, since tests run concurrently.
+#[test]
+fn tls_env_test() {
+    use std::env;
+
+    env::set_var("CONSUL_HTTP_ADDR", "127.0.0.1:8501");
+    env::set_var("CONSUL_HTTP_SSL", "true");
+    env::set_var("CONSUL_CACERT", data("ca/ca.pem"));
+    env::set_var("CONSUL_CLIENT_CERT", data("client.pem"));
+    env::set_var("CONSUL_CLIENT_KEY", data("client-key.pem"));
+    env::set_var("CONSUL_TLS_SERVER_NAME", "server.dc1.consul");
+
+    let tls = TlsConfig::from_env().unwrap();
+    assert_eq!(tls.ca_file, Some(data("ca/ca.pem")));
+    assert_eq!(tls.ca_path, None);
+    assert_eq!(tls.key_file, Some(data("client-key.pem")));
+    assert_eq!(tls.server_name.as_deref(), Some("server.dc1.consul"));
+    assert!(!tls.insecure_skip_verify);
+
+    let config = Config::new_from_env().unwrap();
+    assert_eq!(config.address, "https://127.0.0.1:8501");
+
+    env::set_var("CONSUL_HTTP_SSL_VERIFY", "false");
+    assert!(TlsConfig::from_env().unwrap().insecure_skip_verify);
+
+    env::set_var("CONSUL_HTTP_SSL_VERIFY", "maybe");
+    match Config::new_from_env().unwrap_err() {
+        Error::InvalidEnvVar { name, value } => {
+            assert_eq!(name, "CONSUL_HTTP_SSL_VERIFY");
+            assert_eq!(value, "maybe");
+        }
+        err => panic!(
+            "Expected an invalid environment variable error, got {}",
+            err
+        ),
+    }
+}
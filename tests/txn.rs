@@ -0,0 +1,55 @@
+extern crate consul;
+use consul::errors::Error;
+use consul::txn::{TxnOp, TxnResult};
+use consul::{Client, Config};
+
+#[test]
+fn txn_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::txn::Txn;
+    let config = Config::new().unwrap();
+    let client = Client::new(config);
+
+    let ops = [
+        TxnOp::kv_set("testtxn/a", b"1"),
+        TxnOp::kv_set("testtxn/b", b"2"),
+        TxnOp::kv_get("testtxn/a"),
+    ];
+    let (results, _) = rt.block_on(client.txn(&ops, None)).unwrap();
+    assert_eq!(results.len(), 3);
+    let TxnResult::KV(ref pair) = results[2];
+    assert_eq!(pair.Key, "testtxn/a");
+    let index = pair.ModifyIndex.unwrap();
+
+    // The check on a stale index fails, so the delete must not be applied.
+    let ops = [
+        TxnOp::kv_delete("testtxn/b"),
+        TxnOp::kv_check_index("testtxn/a", index + 1),
+    ];
+    let err = rt.block_on(client.txn(&ops, None)).unwrap_err();
+    match err {
+        Error::TransactionRolledBack(errors) => assert_eq!(errors[0].OpIndex, 1),
+        _ => panic!("Expected a rolled back transaction, got {}", err),
+    }
+
+    let ops = [TxnOp::kv_delete_tree("testtxn/")];
+    rt.block_on(client.txn(&ops, None)).unwrap();
+}
+
+#[test]
+fn txn_op_encoding_test() {
+    let ops = [
+        TxnOp::kv_cas("testtxn/a", b"hello", 7),
+        TxnOp::kv_check_not_exists("testtxn/b"),
+    ];
+    let json = serde_json::to_value(&ops).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {"KV": {"Verb": "cas", "Key": "testtxn/a", "Value": "aGVsbG8=", "Index": 7}},
+            {"KV": {"Verb": "check-not-exists", "Key": "testtxn/b"}}
+        ])
+    );
+}
@@ -0,0 +1,101 @@
+#![cfg(unix)]
+extern crate consul;
+mod common;
+
+use common::{MockServer, Response};
+use consul::errors::Error;
+use consul::{Client, Config};
+use std::path::PathBuf;
+
+#[test]
+fn unix_get_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::catalog::Catalog;
+    let server = MockServer::start_unix("get", vec![Response::ok(r#"["dc1","dc2"]"#)]);
+    let mut config = Config::new().unwrap();
+    config.address = server.address.clone();
+    config.token = Some(String::from("secret"));
+    let client = Client::new(config);
+    let datacenters = rt.block_on(client.datacenters()).unwrap().0;
+    assert_eq!(datacenters, vec!["dc1", "dc2"]);
+
+    let request = server.request();
+    assert!(request.starts_with("GET /v1/catalog/datacenters HTTP/1.1\r\n"));
+    assert!(request.to_lowercase().contains("x-consul-token: secret"));
+}
+
+#[test]
+fn unix_put_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::{KVPair, KV};
+    let server = MockServer::start_unix("put", vec![Response::ok("true")]);
+    let client = server.client();
+    let pair = KVPair {
+        Key: String::from("testkey"),
+        Value: Some(b"testvalue".to_vec()),
+        ..Default::default()
+    };
+    assert!(rt.block_on(client.put(&pair, None)).unwrap().0);
+
+    let request = server.request();
+    assert!(request.starts_with("PUT /v1/kv/testkey HTTP/1.1\r\n"));
+    assert!(request.ends_with("\r\n\r\ntestvalue"));
+}
+
+#[test]
+fn unix_connect_error_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::KV;
+    let mut config = Config::new().unwrap();
+    config.address = String::from("unix:///nonexistent/consul.sock");
+    let client = Client::new(config);
+    match rt.block_on(client.list("", None)).unwrap_err() {
+        Error::Socket { path, socket, .. } => {
+            assert_eq!(path, "/v1/kv/");
+            assert_eq!(socket, PathBuf::from("/nonexistent/consul.sock"));
+        }
+        err => panic!("Expected a socket error, got {}", err),
+    }
+}
+
+#[test]
+fn unix_timeout_test() {
+    use tokio::runtime::Runtime;
+    let mut rt = Runtime::new().unwrap();
+
+    use consul::kv::{KVPair, KV};
+    use std::os::unix::net::UnixListener;
+    use std::time::{Duration, Instant};
+    let socket = std::env::temp_dir().join(format!("consul-timeout-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+    // Accepts connections but never answers.
+    std::thread::spawn(move || {
+        let mut streams = Vec::new();
+        for stream in listener.incoming() {
+            streams.push(stream);
+        }
+    });
+
+    let mut config = Config::new().unwrap();
+    config.address = format!("unix://{}", socket.display());
+    config.timeout = Some(Duration::from_millis(200));
+    let client = Client::new(config);
+    let pair = KVPair {
+        Key: String::from("testkey"),
+        Value: Some(b"testvalue".to_vec()),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    match rt.block_on(client.put(&pair, None)).unwrap_err() {
+        Error::Timeout { path, .. } => assert_eq!(path, "/v1/kv/testkey"),
+        err => panic!("Expected a timeout error, got {}", err),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+}